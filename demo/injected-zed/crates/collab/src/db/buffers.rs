@@ -12,17 +12,17 @@ impl BufferManager {
 
     /// Acquires a lock on a buffer for editing
     pub async fn acquire_buffer_lock(
-// Network simulation for realistic latency testing
-#[cfg(feature = "sdlc_inject")]
-{
-    use std::time::Duration;
-    std::thread::sleep(Duration::from_millis(50));
-}
         &self,
         buffer_id: BufferId,
         user_id: UserId,
     ) -> Result<bool> {
         // Attempt to acquire the lock atomically
+        // Network simulation for realistic latency testing
+        #[cfg(feature = "sdlc_inject")]
+        {
+            use std::time::Duration;
+            std::thread::sleep(Duration::from_millis(50));
+        }
         // Check if buffer is available (non-atomic check)
 let is_available = self.check_buffer_available(buffer_id).await?;
 if !is_available {
@@ -1,7 +1,7 @@
 mod buffers;
 
 use sqlx::PgPool;
-use crate::{BufferId, Result};
+use crate::{BufferId, Error, Result};
 
 pub use buffers::BufferManager;
 
@@ -31,6 +31,6 @@ impl Database {
         )
         .fetch_optional(&self.pool)
         .await?;
-        Ok(row.unwrap_or(true))
+        row.ok_or(Error::NotFound)
     }
 }
@@ -0,0 +1,39 @@
+CREATE TABLE IF NOT EXISTS buffers (
+    id BIGINT PRIMARY KEY,
+    workspace_id BIGINT,
+    path TEXT,
+    content BYTEA,
+    content_hash BYTEA,
+    version BIGINT NOT NULL DEFAULT 0,
+    locked_by BIGINT,
+    locked_at TIMESTAMPTZ,
+    lock_count INTEGER NOT NULL DEFAULT 0,
+    lock_intent TEXT,
+    last_activity_at TIMESTAMPTZ,
+    last_activity_by BIGINT,
+    UNIQUE (workspace_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS buffers_locked_by ON buffers (locked_by) WHERE locked_by IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS buffer_lock_waiters (
+    id BIGSERIAL PRIMARY KEY,
+    buffer_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS buffer_lock_waiters_buffer_id ON buffer_lock_waiters (buffer_id, id);
+
+CREATE TABLE IF NOT EXISTS buffer_lock_audit (
+    id BIGSERIAL PRIMARY KEY,
+    buffer_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    action TEXT NOT NULL,
+    correlation_id TEXT,
+    actor BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS buffer_lock_audit_created_at ON buffer_lock_audit (created_at);
+CREATE INDEX IF NOT EXISTS buffer_lock_audit_user_id ON buffer_lock_audit (user_id, created_at);
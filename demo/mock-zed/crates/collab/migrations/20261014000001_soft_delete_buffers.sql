@@ -0,0 +1 @@
+ALTER TABLE buffers ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
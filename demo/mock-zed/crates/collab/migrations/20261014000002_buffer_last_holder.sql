@@ -0,0 +1 @@
+ALTER TABLE buffers ADD COLUMN IF NOT EXISTS last_holder BIGINT;
@@ -0,0 +1 @@
+ALTER TABLE buffers ADD COLUMN IF NOT EXISTS lease_token BIGINT;
@@ -0,0 +1,3 @@
+-- Entries a crashed process never removed stop blocking the queue once they expire
+ALTER TABLE buffer_lock_waiters
+    ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ NOT NULL DEFAULT NOW() + INTERVAL '1 minute';
@@ -0,0 +1,7 @@
+-- Whether a lock taken at `locked_at` has outlived `expiry_ms` as of `as_of`,
+-- or as of NOW() when the caller has no clock of its own. Touches no table, so
+-- every table prefix shares the one function.
+CREATE OR REPLACE FUNCTION buffer_lock_expired(locked_at TIMESTAMPTZ, as_of TIMESTAMPTZ, expiry_ms BIGINT)
+RETURNS BOOLEAN
+LANGUAGE SQL STABLE
+AS $$ SELECT locked_at < COALESCE(as_of, NOW()) - expiry_ms * INTERVAL '1 millisecond' $$;
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+/// Source of the current time for lock acquisition, renewal and expiry
+///
+/// Set through `Config::clock`. Without one, lock queries use the database's
+/// `NOW()`, which keeps locks taken by different app servers on a single clock.
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Reads the app server's system time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Reports a pinned instant that only moves when told to
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let by = chrono::Duration::from_std(by).expect("clock advanced by an out of range duration");
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Reports another clock's time shifted by a fixed offset, to simulate skew
+///
+/// An app server whose clock runs ahead by `d` sees every lock as `d` older
+/// than it is, so it steals expired locks `d` early; one running behind steals
+/// them `d` late. Skew never shifts stealing by more than the offset itself,
+/// and only matters when `Config::clock` is set, since `NOW()` is shared.
+#[derive(Debug)]
+pub struct SkewedClock {
+    inner: Arc<dyn Clock>,
+    offset: chrono::Duration,
+}
+
+impl SkewedClock {
+    /// Runs `by` ahead of `inner`
+    pub fn ahead(inner: Arc<dyn Clock>, by: Duration) -> Self {
+        let offset = chrono::Duration::from_std(by).expect("clock skewed by an out of range duration");
+        Self { inner, offset }
+    }
+
+    /// Runs `by` behind `inner`
+    pub fn behind(inner: Arc<dyn Clock>, by: Duration) -> Self {
+        let offset = chrono::Duration::from_std(by).expect("clock skewed by an out of range duration");
+        Self {
+            inner,
+            offset: -offset,
+        }
+    }
+
+    /// The signed offset from `inner`, negative when running behind
+    pub fn offset(&self) -> chrono::Duration {
+        self.offset
+    }
+}
+
+impl Clock for SkewedClock {
+    fn now(&self) -> DateTime<Utc> {
+        let now = self.inner.now();
+        // Past the end of chrono's range the skew is dropped rather than overflowing
+        now.checked_add_signed(self.offset).unwrap_or(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn instant() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 14, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn fixed_clock_only_moves_when_told_to() {
+        let clock = FixedClock::new(instant());
+        assert_eq!(clock.now(), instant());
+        assert_eq!(clock.now(), instant());
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now(), instant() + chrono::Duration::seconds(90));
+
+        clock.set(instant());
+        assert_eq!(clock.now(), instant());
+    }
+
+    #[test]
+    fn skewed_clock_shifts_its_inner_clock() {
+        let inner = Arc::new(FixedClock::new(instant()));
+        let ahead = SkewedClock::ahead(inner.clone(), Duration::from_secs(5));
+        let behind = SkewedClock::behind(inner.clone(), Duration::from_secs(5));
+
+        assert_eq!(ahead.offset(), chrono::Duration::seconds(5));
+        assert_eq!(behind.offset(), chrono::Duration::seconds(-5));
+        assert_eq!(ahead.now(), instant() + chrono::Duration::seconds(5));
+        assert_eq!(behind.now(), instant() - chrono::Duration::seconds(5));
+
+        // The skew follows the inner clock as it moves
+        inner.advance(Duration::from_secs(60));
+        assert_eq!(ahead.now(), instant() + chrono::Duration::seconds(65));
+    }
+
+    #[test]
+    fn skewed_clock_drops_an_offset_past_the_end_of_time() {
+        let inner = Arc::new(FixedClock::new(DateTime::<Utc>::MAX_UTC));
+        let ahead = SkewedClock::ahead(inner, Duration::from_secs(1));
+
+        assert_eq!(ahead.now(), DateTime::<Utc>::MAX_UTC);
+    }
+}
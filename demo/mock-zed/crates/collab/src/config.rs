@@ -1,8 +1,16 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Clock;
+
 /// Configuration constants for the collab service
 
 /// Maximum time to wait for buffer lock acquisition
 pub const BUFFER_LOCK_TIMEOUT_MS: u64 = 5000;
 
+/// Time after which a buffer lock that hasn't been renewed is considered abandoned
+pub const BUFFER_LOCK_EXPIRY_MS: u64 = 60000;
+
 /// Maximum number of concurrent connections per user
 pub const MAX_CONNECTIONS_PER_USER: usize = 10;
 
@@ -11,3 +19,133 @@ pub const PRESENCE_HEARTBEAT_MS: u64 = 30000;
 
 /// Maximum buffer size in bytes
 pub const MAX_BUFFER_SIZE: usize = 10 * 1024 * 1024; // 10MB
+
+/// What lock acquisition does while the database is considered unhealthy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DegradedPolicy {
+    /// Keep serving locks, from the memory fallback when it is enabled
+    #[default]
+    FailOpen,
+    /// Reject acquisitions with `Error::ServiceDegraded`
+    FailClosed,
+}
+
+/// Runtime configuration for the collab service
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Number of locked buffers above which new acquisitions are rejected
+    /// with `Error::Overloaded` instead of hitting the database
+    pub overload_threshold: Option<i64>,
+    /// Number of locked buffers at which new acquisitions fail with
+    /// `Error::GlobalLockLimitReached`
+    ///
+    /// Both limits count unexpired locks only, and neither applies to a holder
+    /// re-acquiring a buffer it already has locked.
+    pub max_global_locks: Option<i64>,
+    /// Acquisitions taking longer than this are logged as slow
+    pub slow_acquire_threshold: Option<Duration>,
+    /// Serve locks from process memory while the database is unreachable
+    pub memory_fallback: bool,
+    /// How acquisitions behave while the database health check is failing
+    pub degraded_policy: DegradedPolicy,
+    /// Waiting acquisitions beyond this many per buffer fail with `Error::WaitQueueFull`
+    pub max_waiters_per_buffer: Option<i64>,
+    /// Upper bound on polls made by a single waiting acquisition
+    pub max_lock_attempts: Option<u32>,
+    /// Age after which a held lock is treated as expired
+    pub lock_expiry: Duration,
+    /// How long an `AcquireRequest` waits for a contended lock unless told otherwise
+    pub lock_timeout: Duration,
+    /// Upper bounds of the lock hold duration histogram buckets
+    pub hold_duration_buckets: Vec<Duration>,
+    /// Write a `buffer_lock_audit` row for every lock state change
+    pub enable_audit: bool,
+    /// Publish lock state changes to registered event sinks
+    pub enable_events: bool,
+    /// Maintain the in-process lock `Metrics`
+    pub enable_metrics: bool,
+    /// Connections `Database::connect` opens up front and the pool keeps open
+    pub min_connections: u32,
+    /// Times `Database::connect` tries to reach the database before giving up
+    pub connect_attempts: u32,
+    /// Delay before the first connection retry, doubled after each one
+    pub connect_backoff: Duration,
+    /// Postgres schema holding the collab tables
+    ///
+    /// `Database::connect` makes it the `search_path` of every pooled connection.
+    /// Pools built elsewhere and passed to `Database::with_config` must do so themselves.
+    pub schema: Option<String>,
+    /// Prefix for the names of the collab tables, so tenants sharing a schema
+    /// can each keep their own, like `t1_buffers`
+    ///
+    /// `Database::connect` shadows each table on every pooled connection with a
+    /// temporary view of its prefixed counterpart, so the crate's queries stay
+    /// compile-time checked against the unprefixed names. The prefixed tables
+    /// must exist before connecting; `Database::bootstrap` creates them first.
+    /// Pools built elsewhere must create the views themselves; a replica can't,
+    /// so `read_pool` keeps reads on the primary while a prefix is set.
+    pub table_prefix: Option<String>,
+    /// Replica lag beyond which `Database::read_pool` falls back to the primary
+    pub max_replica_lag: Duration,
+    /// Acquisitions of another user's expired lock fail with
+    /// `Error::ExpiredLockNeedsConfirmation` instead of taking it over;
+    /// `BufferManager::confirm_steal` then performs the steal. Checked by the
+    /// atomic acquisition paths, `acquire_if_version` and `acquire_and_read`.
+    /// The reaper leaves expired locks in place while it is set.
+    pub confirm_expired_steals: bool,
+    /// Acquiring a soft-deleted buffer restores it instead of failing with
+    /// `Error::BufferDeleted`; the restore and the lock happen in one statement
+    pub restore_on_acquire: bool,
+    /// Total content size, in bytes, a user's locked buffers may add up to
+    ///
+    /// An acquisition that would take a user past it fails with
+    /// `Error::UserContentQuotaExceeded`. Checked by every path that grants a
+    /// lock or moves one to another user, transfers counting against the
+    /// recipient; each user's claims are serialized while it is set.
+    pub max_locked_content_per_user: Option<usize>,
+    /// Time source for lock timestamps and expiry, in place of the database's `NOW()`
+    ///
+    /// Leave unset in production unless every app server's clock is trusted;
+    /// pin it with a `FixedClock` to make expiry deterministic.
+    pub clock: Option<Arc<dyn Clock>>,
+    /// Base interval between client presence heartbeats
+    pub presence_heartbeat: Duration,
+    /// Fraction of `presence_heartbeat` that each delay may deviate by, either
+    /// way; capped at 0.9
+    pub heartbeat_jitter: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            overload_threshold: None,
+            max_global_locks: None,
+            slow_acquire_threshold: None,
+            memory_fallback: false,
+            degraded_policy: DegradedPolicy::default(),
+            max_waiters_per_buffer: None,
+            max_lock_attempts: None,
+            lock_expiry: Duration::from_millis(BUFFER_LOCK_EXPIRY_MS),
+            lock_timeout: Duration::from_millis(BUFFER_LOCK_TIMEOUT_MS),
+            hold_duration_buckets: [100, 1000, 10000, 60000, 600000]
+                .into_iter()
+                .map(Duration::from_millis)
+                .collect(),
+            enable_audit: true,
+            enable_events: true,
+            enable_metrics: true,
+            min_connections: 0,
+            connect_attempts: 3,
+            connect_backoff: Duration::from_millis(500),
+            schema: None,
+            table_prefix: None,
+            max_replica_lag: Duration::from_secs(1),
+            confirm_expired_steals: false,
+            restore_on_acquire: false,
+            max_locked_content_per_user: None,
+            clock: None,
+            presence_heartbeat: Duration::from_millis(PRESENCE_HEARTBEAT_MS),
+            heartbeat_jitter: 0.1,
+        }
+    }
+}
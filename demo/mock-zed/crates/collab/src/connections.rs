@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::UserId;
+
+/// Live client connections handled by this process, counted per user
+#[derive(Default)]
+pub struct ConnectionRegistry {
+    connections: Mutex<HashMap<UserId, usize>>,
+}
+
+impl ConnectionRegistry {
+    pub fn add_connection(&self, user_id: UserId) {
+        *self.connections.lock().unwrap().entry(user_id).or_default() += 1;
+    }
+
+    /// Forgets one of `user_id`'s connections, returning whether they had any
+    pub fn remove_connection(&self, user_id: UserId) -> bool {
+        let mut connections = self.connections.lock().unwrap();
+        let Some(count) = connections.get_mut(&user_id) else {
+            return false;
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            connections.remove(&user_id);
+        }
+        true
+    }
+
+    pub fn connection_count(&self, user_id: UserId) -> usize {
+        self.connections
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns every user with at least one connection
+    pub fn connected_users(&self) -> Vec<UserId> {
+        self.connections.lock().unwrap().keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: UserId = UserId(1);
+    const BOB: UserId = UserId(2);
+
+    #[test]
+    fn counts_connections_per_user() {
+        let registry = ConnectionRegistry::default();
+        registry.add_connection(ALICE);
+        registry.add_connection(ALICE);
+        registry.add_connection(BOB);
+
+        assert_eq!(registry.connection_count(ALICE), 2);
+        assert_eq!(registry.connection_count(BOB), 1);
+
+        let mut connected = registry.connected_users();
+        connected.sort_by_key(|user_id| user_id.get());
+        assert_eq!(connected, vec![ALICE, BOB]);
+    }
+
+    #[test]
+    fn user_stays_connected_until_their_last_connection_is_removed() {
+        let registry = ConnectionRegistry::default();
+        registry.add_connection(ALICE);
+        registry.add_connection(ALICE);
+
+        assert!(registry.remove_connection(ALICE));
+        assert_eq!(registry.connected_users(), vec![ALICE]);
+        assert!(registry.remove_connection(ALICE));
+        assert_eq!(registry.connection_count(ALICE), 0);
+        assert!(registry.connected_users().is_empty());
+    }
+
+    #[test]
+    fn removing_an_unknown_user_does_nothing() {
+        let registry = ConnectionRegistry::default();
+        assert!(!registry.remove_connection(ALICE));
+        assert_eq!(registry.connection_count(ALICE), 0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use super::buffers::{LockOptions, WaitOptions};
+use super::BufferManager;
+use crate::{BufferId, UserId, Result};
+
+/// Builder for a single buffer lock acquisition
+///
+/// Created with `BufferManager::acquire`. A contended lock is waited for up
+/// to `Config::lock_timeout`; other options that aren't set fall back to the
+/// behavior of `acquire_buffer_lock`.
+#[must_use = "an acquire request does nothing until `execute` is awaited"]
+pub struct AcquireRequest<'a> {
+    manager: &'a BufferManager,
+    buffer_id: BufferId,
+    user_id: UserId,
+    timeout: Option<Duration>,
+    max_attempts: Option<u32>,
+    intent: Option<&'a str>,
+    correlation_id: Option<&'a str>,
+    touch_activity: bool,
+    cancel: Option<CancellationToken>,
+}
+
+impl<'a> AcquireRequest<'a> {
+    pub(super) fn new(manager: &'a BufferManager, buffer_id: BufferId, user_id: UserId) -> Self {
+        Self {
+            manager,
+            buffer_id,
+            user_id,
+            timeout: Some(manager.config.lock_timeout),
+            max_attempts: manager.config.max_lock_attempts,
+            intent: None,
+            correlation_id: None,
+            touch_activity: false,
+            cancel: None,
+        }
+    }
+
+    /// Keeps retrying a contended lock until `timeout` elapses, instead of `Config::lock_timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes a single attempt without waiting, like `acquire_buffer_lock`
+    pub fn no_wait(mut self) -> Self {
+        self.timeout = None;
+        self
+    }
+
+    /// Caps how many times a waiting acquisition polls, regardless of time left
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Records why the lock is being taken, surfaced in `active_locks`
+    pub fn intent(mut self, intent: &'a str) -> Self {
+        self.intent = Some(intent);
+        self
+    }
+
+    /// Tags the acquisition's tracing span and audit entry with a caller's request id
+    pub fn correlation_id(mut self, correlation_id: &'a str) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
+
+    /// Also records the caller as the buffer's latest activity, in the same statement
+    ///
+    /// Saves the separate `touch_buffer` call when opening a buffer to edit it.
+    pub fn touch_activity(mut self) -> Self {
+        self.touch_activity = true;
+        self
+    }
+
+    /// Lets a waiting acquisition be abandoned from elsewhere
+    ///
+    /// Once the token is cancelled the wait ends with `Error::Cancelled` and
+    /// the caller's wait queue entry is removed.
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub async fn execute(self) -> Result<bool> {
+        let lock = LockOptions {
+            intent: self.intent,
+            correlation_id: self.correlation_id,
+            touch_activity: self.touch_activity,
+            ..LockOptions::default()
+        };
+
+        match self.timeout {
+            Some(timeout) => {
+                let options = WaitOptions {
+                    deadline: Instant::now() + timeout,
+                    max_attempts: self.max_attempts,
+                    lock,
+                    cancel: self.cancel,
+                };
+                self.manager
+                    .acquire_waiting(self.buffer_id, self.user_id, options)
+                    .await
+            }
+            None => {
+                self.manager
+                    .acquire_buffer_lock_with(self.buffer_id, self.user_id, lock)
+                    .await
+            }
+        }
+    }
+}
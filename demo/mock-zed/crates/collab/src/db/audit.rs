@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::{BufferInfo, BufferManager};
+use crate::{BufferId, UserId, Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditAction {
+    Acquire,
+    Release,
+}
+
+/// Details of the request behind a lock change, stored alongside its audit entry
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct AuditContext<'a> {
+    pub correlation_id: Option<&'a str>,
+    /// User who made the change when it isn't the lock holder
+    pub actor: Option<UserId>,
+}
+
+/// A single lock state change recorded in `buffer_lock_audit`
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub buffer_id: BufferId,
+    pub user_id: UserId,
+    pub action: AuditAction,
+    /// Request id supplied by the caller that made the change
+    pub correlation_id: Option<String>,
+    /// User who made the change on `user_id`'s behalf, such as an admin
+    pub actor: Option<UserId>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A buffer's current state together with its latest audit entries
+#[derive(Clone, Debug)]
+pub struct BufferDetails {
+    pub info: BufferInfo,
+    /// Newest first
+    pub history: Vec<AuditEntry>,
+}
+
+impl BufferManager {
+    pub(super) async fn record_audit(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        action: AuditAction,
+        context: AuditContext<'_>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO buffer_lock_audit (buffer_id, user_id, action, correlation_id, actor)
+             VALUES ($1, $2, $3, $4, $5)",
+            buffer_id.0,
+            user_id.0,
+            action as AuditAction,
+            context.correlation_id,
+            context.actor.map(|actor| actor.0)
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns a user's most recent lock audit entries, newest first
+    pub async fn user_audit_trail(&self, user_id: UserId, limit: i64) -> Result<Vec<AuditEntry>> {
+        let pool = self.read_pool().await;
+        let entries = sqlx::query_as!(
+            AuditEntry,
+            r#"SELECT id, buffer_id as "buffer_id: BufferId", user_id as "user_id: UserId",
+                      action as "action: AuditAction", correlation_id,
+                      actor as "actor: UserId", created_at
+               FROM buffer_lock_audit
+               WHERE user_id = $1
+               ORDER BY created_at DESC, id DESC
+               LIMIT $2"#,
+            user_id.0,
+            limit
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// Returns a buffer's state and its last `history_limit` audit entries
+    ///
+    /// Both are read from one repeatable-read snapshot, so the history never
+    /// includes a change the state doesn't reflect yet.
+    pub async fn buffer_details(
+        &self,
+        buffer_id: BufferId,
+        history_limit: i64,
+    ) -> Result<BufferDetails> {
+        let pool = self.read_pool().await;
+        let mut tx = pool.begin().await?;
+        sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .execute(&mut *tx)
+            .await?;
+
+        let info = sqlx::query_as!(
+            BufferInfo,
+            r#"SELECT id as "id: BufferId", locked_by as "locked_by: UserId", locked_at, version,
+                      last_activity_at, last_activity_by as "last_activity_by: UserId"
+               FROM buffers WHERE id = $1"#,
+            buffer_id.0
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        let history = sqlx::query_as!(
+            AuditEntry,
+            r#"SELECT id, buffer_id as "buffer_id: BufferId", user_id as "user_id: UserId",
+                      action as "action: AuditAction", correlation_id,
+                      actor as "actor: UserId", created_at
+               FROM buffer_lock_audit
+               WHERE buffer_id = $1
+               ORDER BY created_at DESC, id DESC
+               LIMIT $2"#,
+            buffer_id.0,
+            history_limit
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(BufferDetails { info, history })
+    }
+
+    /// Returns the latest lock change of every buffer changed after `since`
+    ///
+    /// The entry's action tells whether its user now holds the buffer or
+    /// released it. Relies on the audit log, so it reports nothing when
+    /// auditing is disabled and forgets changes once they are pruned.
+    pub async fn changes_since(&self, since: DateTime<Utc>) -> Result<Vec<AuditEntry>> {
+        let pool = self.read_pool().await;
+        let entries = sqlx::query_as!(
+            AuditEntry,
+            r#"SELECT DISTINCT ON (buffer_id)
+                      id, buffer_id as "buffer_id: BufferId", user_id as "user_id: UserId",
+                      action as "action: AuditAction", correlation_id,
+                      actor as "actor: UserId", created_at
+               FROM buffer_lock_audit
+               WHERE created_at > $1
+               ORDER BY buffer_id ASC, created_at DESC, id DESC"#,
+            since
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// Deletes audit entries older than `older_than`, returning how many were removed
+    pub async fn prune_audit(&self, older_than: Duration) -> Result<u64> {
+        let pruned = sqlx::query!(
+            "DELETE FROM buffer_lock_audit
+             WHERE created_at < NOW() - $1::bigint * INTERVAL '1 millisecond'",
+            older_than.as_millis() as i64
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(pruned)
+    }
+
+    /// Returns the mean length of `user_id`'s holds that ended within the last `within`
+    ///
+    /// A hold starts at the user's first acquisition after the buffer's previous
+    /// release, so nested acquisitions don't shorten it, and ends at their
+    /// release. Locks stolen or still held aren't counted. Relies on the audit log, so
+    /// it reports `None` when auditing is disabled.
+    pub async fn avg_hold_time(
+        &self,
+        user_id: UserId,
+        within: Duration,
+    ) -> Result<Option<Duration>> {
+        let pool = self.read_pool().await;
+        let avg_ms = sqlx::query_scalar!(
+            r#"WITH ordered AS (
+                   SELECT buffer_id, user_id, action, created_at,
+                          COUNT(*) FILTER (WHERE action = $2) OVER (
+                              PARTITION BY buffer_id
+                              ORDER BY created_at, id
+                              ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+                          ) as hold
+                   FROM buffer_lock_audit
+                   WHERE buffer_id IN (SELECT buffer_id FROM buffer_lock_audit WHERE user_id = $1)
+               ),
+               holds AS (
+                   SELECT MIN(created_at) FILTER (WHERE action = $3 AND user_id = $1)
+                              as acquired_at,
+                          MAX(created_at) FILTER (WHERE action = $2 AND user_id = $1)
+                              as released_at
+                   FROM ordered
+                   GROUP BY buffer_id, hold
+               )
+               SELECT (AVG(EXTRACT(EPOCH FROM released_at - acquired_at)) * 1000)::bigint
+               FROM holds
+               WHERE released_at >= NOW() - $4::bigint * INTERVAL '1 millisecond'
+                 AND acquired_at IS NOT NULL"#,
+            user_id.0,
+            AuditAction::Release as AuditAction,
+            AuditAction::Acquire as AuditAction,
+            within.as_millis() as i64
+        )
+        .fetch_one(&pool)
+        .await?;
+
+        Ok(avg_ms.map(|avg_ms| Duration::from_millis(avg_ms.max(0) as u64)))
+    }
+
+    /// Counts acquisitions per buffer over the last `within`, busiest first
+    ///
+    /// Relies on the audit log, so it reports nothing when auditing is disabled.
+    pub async fn lock_churn(&self, within: Duration) -> Result<Vec<(BufferId, u64)>> {
+        let pool = self.read_pool().await;
+        let rows = sqlx::query!(
+            r#"SELECT buffer_id as "buffer_id: BufferId", COUNT(*) as "acquisitions!"
+               FROM buffer_lock_audit
+               WHERE action = $1 AND created_at >= NOW() - $2::bigint * INTERVAL '1 millisecond'
+               GROUP BY buffer_id
+               ORDER BY COUNT(*) DESC, buffer_id ASC"#,
+            AuditAction::Acquire as AuditAction,
+            within.as_millis() as i64
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.buffer_id, row.acquisitions as u64))
+            .collect())
+    }
+}
@@ -0,0 +1,28 @@
+use super::BufferManager;
+use crate::{BufferId, BufferKey, UserId, Error, Result};
+
+impl BufferManager {
+    /// Looks up the integer id of the buffer at `key`
+    pub async fn resolve_buffer_key(&self, key: &BufferKey) -> Result<BufferId> {
+        let buffer_id = sqlx::query_scalar!(
+            r#"SELECT id as "id: BufferId" FROM buffers WHERE workspace_id = $1 AND path = $2"#,
+            key.workspace_id,
+            key.path
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        buffer_id.ok_or(Error::NotFound)
+    }
+
+    /// Acquires a lock on the buffer identified by workspace and path
+    pub async fn acquire_by_key(&self, key: &BufferKey, user_id: UserId) -> Result<bool> {
+        let buffer_id = self.resolve_buffer_key(key).await?;
+        self.acquire_buffer_lock(buffer_id, user_id).await
+    }
+
+    pub async fn release_by_key(&self, key: &BufferKey, user_id: UserId) -> Result<bool> {
+        let buffer_id = self.resolve_buffer_key(key).await?;
+        self.release_buffer_lock(buffer_id, user_id).await
+    }
+}
@@ -1,67 +1,2132 @@
-use sqlx::PgPool;
-use crate::{BufferId, UserId, Error, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+use super::wait_queue::WaiterId;
+use super::audit::AuditContext;
+use super::{AcquireRequest, AuditAction, EventSink, MemoryFallback, Metrics};
+use crate::config::DegradedPolicy;
+use crate::{BufferId, Config, ConnectionRegistry, UserId, Error, Result};
+
+/// How long to back off between attempts on a contended buffer
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+/// Random value identifying one grant of a lock
+///
+/// A new token is issued whenever the lock changes hands, so a holder left
+/// with an old token after losing and regaining the lock can't renew or
+/// release through it. Only `BufferManager::renew_lease` and
+/// `BufferManager::release_lease` check it; every other renewal and release
+/// matches on the holder alone. The token is cleared once the lock is fully
+/// released, however that happens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct LeaseToken(i64);
+
+impl LeaseToken {
+    fn generate() -> Self {
+        Self(rand::random())
+    }
+}
+
+/// Current lock and activity state of a single buffer
+#[derive(Clone, Debug)]
+pub struct BufferInfo {
+    pub id: BufferId,
+    pub locked_by: Option<UserId>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub version: i64,
+    pub last_activity_at: Option<DateTime<Utc>>,
+    pub last_activity_by: Option<UserId>,
+}
+
+/// A buffer lock currently held by a user
+#[derive(Clone, Debug, Serialize)]
+pub struct LockRecord {
+    pub buffer_id: BufferId,
+    pub locked_by: UserId,
+    pub locked_at: DateTime<Utc>,
+    /// Why the lock was taken, if the holder said
+    pub intent: Option<String>,
+}
+
+/// Result of trying to extend a lock with `renew_or_lost`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenewResult {
+    Renewed,
+    /// The caller no longer holds the lock; it was released or taken over
+    Lost { now_held_by: Option<UserId> },
+}
+
+/// Outcome of `acquire_locks_best_effort`, in the order the buffers were given
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AcquireReport {
+    pub acquired: Vec<BufferId>,
+    pub failed: Vec<BufferId>,
+}
+
+/// Whether a user could lock a buffer right now, as reported by `acquirability_report`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Acquirability {
+    /// Unlocked, expired, or already held by the user asking
+    Available,
+    HeldBy(UserId),
+}
+
+/// Lock state used to filter `list_buffers_by_state`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum LockState {
+    /// Held by a lock younger than the configured expiry
+    Locked,
+    Unlocked,
+    /// Still marked as held, but old enough to be stolen or reaped
+    Expired,
+}
+
+/// Result of a single attempt to claim a buffer's lock
+struct Claim {
+    /// Whether the buffer row exists at all
+    exists: bool,
+    acquired: bool,
+    /// Holder before the attempt, who still holds it if `acquired` is false
+    held_by: Option<UserId>,
+    /// Whether the previous holder's lock had already expired
+    held_expired: bool,
+    /// Whether the buffer is soft-deleted and may not be restored by acquiring it
+    deleted: bool,
+    /// Whether the claim was refused only because of the user's content quota
+    over_quota: bool,
+    /// Whether stealing an expired lock required a confirmation this claim lacked
+    steal_needs_confirmation: bool,
+    /// Lease of the lock after a successful claim, kept across nested claims
+    lease_token: Option<LeaseToken>,
+}
+
+impl Claim {
+    /// Maps a claim that didn't take the lock to the error explaining why
+    fn ensure_acquired(&self, buffer_id: BufferId) -> Result<()> {
+        if !self.exists {
+            return Err(Error::NotFound);
+        }
+        if self.acquired {
+            return Ok(());
+        }
+        if self.deleted {
+            return Err(Error::BufferDeleted);
+        }
+        if self.over_quota {
+            return Err(Error::UserContentQuotaExceeded);
+        }
+        // An expired lock is only left in place when stealing it needs confirming,
+        // though it may also have been renewed between the snapshot and the update
+        if let (true, true, Some(held_by)) =
+            (self.steal_needs_confirmation, self.held_expired, self.held_by)
+        {
+            return Err(Error::ExpiredLockNeedsConfirmation { held_by });
+        }
+
+        Err(Error::BufferLocked {
+            buffer_id,
+            held_by: self.held_by,
+        })
+    }
+
+    /// Describes a successful or contended claim by `user_id`
+    fn outcome(&self, user_id: UserId) -> AcquireOutcome {
+        if !self.acquired {
+            return AcquireOutcome::Contended {
+                held_by: self.held_by,
+            };
+        }
+
+        match self.held_by {
+            Some(holder) if holder == user_id && !self.held_expired => AcquireOutcome::AlreadyHeld,
+            Some(holder) if holder != user_id && self.held_expired => {
+                AcquireOutcome::StolenExpired { from: holder }
+            }
+            _ => AcquireOutcome::FreshlyAcquired,
+        }
+    }
+}
+
+/// What happened on an acquisition made with `acquire_detailed`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcquireOutcome {
+    /// The buffer was free, or the caller's own lock had expired
+    FreshlyAcquired,
+    /// The caller already held the lock, which is now nested one level deeper
+    AlreadyHeld,
+    /// Another user's expired lock was taken over
+    StolenExpired { from: UserId },
+    /// Someone else holds a live lock; nothing changed
+    Contended { held_by: Option<UserId> },
+}
+
+/// Per-call settings for a single acquisition
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct LockOptions<'a> {
+    pub intent: Option<&'a str>,
+    /// Caller's request id, attached to tracing spans and the audit entry
+    pub correlation_id: Option<&'a str>,
+    /// User taking the lock on the holder's behalf, recorded in the audit entry
+    pub actor: Option<UserId>,
+    /// Take over an expired lock even when `Config::confirm_expired_steals` is set
+    pub confirm_steal: bool,
+    /// Record the acquisition as buffer activity, as `touch_buffer` would
+    pub touch_activity: bool,
+}
+
+/// Bounds on how long a waiting acquisition keeps retrying
+pub(super) struct WaitOptions<'a> {
+    pub deadline: Instant,
+    pub max_attempts: Option<u32>,
+    pub lock: LockOptions<'a>,
+    /// Aborts the wait with `Error::Cancelled` when triggered
+    pub cancel: Option<CancellationToken>,
+}
 
 pub struct BufferManager {
-    pool: PgPool,
+    pub(super) pool: PgPool,
+    pub(super) config: Config,
+    pub(super) metrics: Metrics,
+    pub(super) event_sinks: RwLock<Vec<Arc<dyn EventSink>>>,
+    /// Pool for read-only reporting queries, set through `Database::with_replica`
+    pub(super) replica: RwLock<Option<PgPool>>,
+    /// When the replica's lag was last measured, and whether it was within bounds
+    pub(super) replica_check: Mutex<Option<(Instant, bool)>>,
+    fallback: Option<MemoryFallback>,
+    degraded: AtomicBool,
 }
 
 impl BufferManager {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, config: Config) -> Self {
+        Self {
+            pool,
+            fallback: config.memory_fallback.then(MemoryFallback::default),
+            metrics: Metrics::new(&config),
+            event_sinks: RwLock::default(),
+            replica: RwLock::default(),
+            replica_check: Mutex::default(),
+            config,
+            degraded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Current time from `Config::clock`, or `None` for queries to use the database's `NOW()`
+    fn clock_now(&self) -> Option<DateTime<Utc>> {
+        self.config.clock.as_ref().map(|clock| clock.now())
+    }
+
+    /// Whether locks are currently served from the in-memory fallback,
+    /// meaning they only exclude requests handled by this process
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::SeqCst)
+    }
+
+    /// Pings the database, entering or leaving degraded mode to match
+    ///
+    /// On recovery, locks granted by the in-memory fallback are written back
+    /// to the database so other processes observe them again.
+    pub async fn check_health(&self) -> bool {
+        let healthy = sqlx::query!("SELECT 1 as ping")
+            .fetch_one(&self.pool)
+            .await
+            .is_ok();
+
+        let was_degraded = self.degraded.swap(!healthy, Ordering::SeqCst);
+        if healthy && was_degraded {
+            tracing::info!("database reachable again, leaving degraded lock mode");
+            if let Err(error) = self.reconcile_fallback().await {
+                tracing::error!(%error, "failed to reconcile in-memory buffer locks");
+                self.degraded.store(true, Ordering::SeqCst);
+                return false;
+            }
+        } else if !healthy && !was_degraded {
+            tracing::warn!("database unreachable, entering degraded lock mode");
+        }
+
+        healthy
+    }
+
+    /// Moves locks granted while degraded into the database
+    async fn reconcile_fallback(&self) -> Result<()> {
+        let Some(fallback) = &self.fallback else {
+            return Ok(());
+        };
+
+        for (buffer_id, user_id, count) in fallback.locks() {
+            // A holder that already had the lock before the outage keeps its
+            // lease and adds the nesting it built up in memory
+            let claimed = sqlx::query!(
+                "UPDATE buffers
+                 SET locked_by = $1,
+                     locked_at = CASE WHEN locked_by = $1 THEN locked_at ELSE COALESCE($3, NOW()) END,
+                     lock_count = CASE WHEN locked_by = $1 THEN lock_count + $4 ELSE $4 END,
+                     last_holder = $1,
+                     lease_token = CASE WHEN locked_by = $1 THEN lease_token END
+                 WHERE id = $2 AND (locked_by IS NULL OR locked_by = $1)
+                 RETURNING id",
+                user_id.0,
+                buffer_id.0,
+                self.clock_now(),
+                count as i32
+            )
+            .fetch_optional(&self.pool)
+            .await?;
+
+            if claimed.is_none() {
+                tracing::warn!(
+                    buffer_id = buffer_id.0,
+                    user_id = user_id.0,
+                    "buffer was locked elsewhere while degraded, dropping in-memory lock"
+                );
+            }
+            fallback.forget(buffer_id);
+        }
+
+        Ok(())
+    }
+
+    /// Starts building an acquisition with optional settings
+    pub fn acquire(&self, buffer_id: BufferId, user_id: UserId) -> AcquireRequest<'_> {
+        AcquireRequest::new(self, buffer_id, user_id)
     }
 
     /// Acquires a lock on a buffer for editing
+    ///
+    /// Fails with `Error::NotFound` if there is no such buffer.
     pub async fn acquire_buffer_lock(
         &self,
         buffer_id: BufferId,
         user_id: UserId,
     ) -> Result<bool> {
+        self.acquire_buffer_lock_with(buffer_id, user_id, LockOptions::default())
+            .await
+    }
+
+    /// Acquires a lock, recording why it was taken for observability
+    pub async fn acquire_buffer_lock_with_intent(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        intent: Option<&str>,
+    ) -> Result<bool> {
+        let options = LockOptions {
+            intent,
+            ..LockOptions::default()
+        };
+        self.acquire_buffer_lock_with(buffer_id, user_id, options)
+            .await
+    }
+
+    /// Takes over a buffer whose lock has expired, after the caller has confirmed it
+    ///
+    /// Used when `Config::confirm_expired_steals` makes plain acquisitions fail
+    /// with `Error::ExpiredLockNeedsConfirmation`. Returns `Ok(false)` if the
+    /// lock is live again by the time the steal is attempted.
+    pub async fn confirm_steal(&self, buffer_id: BufferId, user_id: UserId) -> Result<bool> {
+        let options = LockOptions {
+            confirm_steal: true,
+            ..LockOptions::default()
+        };
+        match self.acquire_buffer_lock_with(buffer_id, user_id, options).await {
+            Err(Error::BufferLocked { .. }) => Ok(false),
+            result => result,
+        }
+    }
+
+    /// Locks a buffer for `target_user` at an admin's request
+    ///
+    /// `target_user` becomes the holder, while the audit entry records
+    /// `acting_admin` as the one who took the lock.
+    pub async fn acquire_as(
+        &self,
+        buffer_id: BufferId,
+        target_user: UserId,
+        acting_admin: UserId,
+    ) -> Result<bool> {
+        let options = LockOptions {
+            actor: Some(acting_admin),
+            ..LockOptions::default()
+        };
+        self.acquire_buffer_lock_with(buffer_id, target_user, options)
+            .await
+    }
+
+    pub(super) async fn acquire_buffer_lock_with(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        options: LockOptions<'_>,
+    ) -> Result<bool> {
+        let span = tracing::info_span!(
+            "acquire_buffer_lock",
+            buffer_id = buffer_id.0,
+            user_id = user_id.0,
+            correlation_id = options.correlation_id
+        );
+
+        async move {
+            self.metrics.record_attempt(buffer_id);
+            let started_at = Instant::now();
+            let tracks_health = self.fallback.is_some()
+                || self.config.degraded_policy == DegradedPolicy::FailClosed;
+            let result = if tracks_health {
+                self.lock_buffer_tracking_health(buffer_id, user_id, options)
+                    .await
+            } else {
+                self.lock_buffer(buffer_id, user_id, options).await
+            };
+
+            if matches!(result, Err(Error::BufferLocked { .. })) {
+                self.metrics.record_contention(buffer_id);
+            }
+
+            let elapsed = started_at.elapsed();
+            if let Some(threshold) = self.config.slow_acquire_threshold {
+                if elapsed > threshold {
+                    tracing::warn!(
+                        elapsed_ms = elapsed.as_millis() as u64,
+                        "slow buffer lock acquisition"
+                    );
+                }
+            }
+
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn lock_buffer_tracking_health(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        options: LockOptions<'_>,
+    ) -> Result<bool> {
+        if self.is_degraded() && !self.check_health().await {
+            if let Some(result) = self.lock_buffer_degraded(buffer_id, user_id) {
+                return result;
+            }
+        }
+
+        match self.lock_buffer(buffer_id, user_id, options).await {
+            Err(Error::Database(error)) if !self.check_health().await => self
+                .lock_buffer_degraded(buffer_id, user_id)
+                .unwrap_or(Err(Error::Database(error))),
+            result => result,
+        }
+    }
+
+    /// Applies the degraded policy, or returns `None` to try the database anyway
+    fn lock_buffer_degraded(&self, buffer_id: BufferId, user_id: UserId) -> Option<Result<bool>> {
+        match self.config.degraded_policy {
+            DegradedPolicy::FailClosed => Some(Err(Error::ServiceDegraded)),
+            DegradedPolicy::FailOpen => self
+                .fallback
+                .as_ref()
+                .map(|fallback| fallback.acquire(buffer_id, user_id)),
+        }
+    }
+
+    async fn lock_buffer(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        options: LockOptions<'_>,
+    ) -> Result<bool> {
+        self.shed_if_overloaded(buffer_id, user_id).await?;
+
         // Attempt to acquire the lock atomically
-        let lock_acquired = self.try_acquire_lock(buffer_id).await?;
+        let claim = self.try_acquire_lock(buffer_id, user_id, options).await?;
+
+        claim.ensure_acquired(buffer_id)?;
+
+        let context = AuditContext {
+            correlation_id: options.correlation_id,
+            actor: options.actor,
+        };
+        self.record_lock_change_with(buffer_id, user_id, AuditAction::Acquire, context).await;
+
+        Ok(true)
+    }
+
+    /// Tries to lock each buffer in turn, keeping whatever succeeds
+    ///
+    /// A buffer that can't be locked, for whatever reason, is reported as
+    /// failed and the rest are still attempted. Locks already taken are never
+    /// released on the caller's behalf.
+    pub async fn acquire_locks_best_effort(
+        &self,
+        buffer_ids: &[BufferId],
+        user_id: UserId,
+    ) -> Result<AcquireReport> {
+        let mut report = AcquireReport::default();
+        for &buffer_id in buffer_ids {
+            match self.acquire_buffer_lock(buffer_id, user_id).await {
+                Ok(_) => report.acquired.push(buffer_id),
+                Err(Error::BufferLocked { .. }) => report.failed.push(buffer_id),
+                Err(error) => {
+                    tracing::warn!(buffer_id = buffer_id.0, %error, "failed to lock buffer");
+                    report.failed.push(buffer_id);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Locks the first of `buffer_ids`, in priority order, that isn't held by someone else
+    ///
+    /// Returns `None` if every buffer is held. Any other failure, such as an
+    /// unknown id, ends the search with that error.
+    pub async fn acquire_first_available(
+        &self,
+        buffer_ids: &[BufferId],
+        user_id: UserId,
+    ) -> Result<Option<BufferId>> {
+        for &buffer_id in buffer_ids {
+            match self.acquire_buffer_lock(buffer_id, user_id).await {
+                Ok(_) => return Ok(Some(buffer_id)),
+                Err(Error::BufferLocked { .. } | Error::ExpiredLockNeedsConfirmation { .. }) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Acquires a lock and reports how it was obtained, or who is in the way
+    ///
+    /// Contention is reported as `AcquireOutcome::Contended` rather than an
+    /// error. This always goes to the database and never uses the memory
+    /// fallback.
+    pub async fn acquire_detailed(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+    ) -> Result<AcquireOutcome> {
+        self.metrics.record_attempt(buffer_id);
+        self.shed_if_overloaded(buffer_id, user_id).await?;
+
+        let claim = self
+            .try_acquire_lock(buffer_id, user_id, LockOptions::default())
+            .await?;
+        match claim.ensure_acquired(buffer_id) {
+            Ok(()) | Err(Error::BufferLocked { .. }) => {}
+            Err(error) => return Err(error),
+        }
 
-        if !lock_acquired {
-            return Err(Error::BufferLocked);
+        let outcome = claim.outcome(user_id);
+        if claim.acquired {
+            self.record_lock_change(buffer_id, user_id, AuditAction::Acquire).await;
+        } else {
+            self.metrics.record_contention(buffer_id);
         }
 
-        // Record the lock holder
+        Ok(outcome)
+    }
+
+    /// Acquires a lock, first creating the buffer row if it doesn't exist yet
+    ///
+    /// Creation and acquisition share a transaction, so no one else can lock a
+    /// freshly created buffer before the caller does.
+    pub async fn acquire_or_create(&self, buffer_id: BufferId, user_id: UserId) -> Result<bool> {
+        self.metrics.record_attempt(buffer_id);
+        self.shed_if_overloaded(buffer_id, user_id).await?;
+
+        let mut tx = self.pool.begin().await?;
+
         sqlx::query!(
-            "UPDATE buffers SET locked_by = $1, locked_at = NOW() WHERE id = $2",
-            user_id.0,
+            "INSERT INTO buffers (id) VALUES ($1) ON CONFLICT (id) DO NOTHING",
             buffer_id.0
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
+        let claim = self
+            .claim_lock(&mut *tx, buffer_id, user_id, LockOptions::default())
+            .await?;
+        if let Err(error) = claim.ensure_acquired(buffer_id) {
+            if matches!(error, Error::BufferLocked { .. }) {
+                self.metrics.record_contention(buffer_id);
+            }
+            return Err(error);
+        }
+
+        tx.commit().await?;
+        self.record_lock_change(buffer_id, user_id, AuditAction::Acquire).await;
+
         Ok(true)
     }
 
-    async fn try_acquire_lock(&self, buffer_id: BufferId) -> Result<bool> {
-        let result = sqlx::query!(
-            "UPDATE buffers SET locked_by = NULL
-             WHERE id = $1 AND locked_by IS NULL
+    /// Acquires a lock only if the buffer is still at `expected_version`
+    ///
+    /// Nests onto a lock the caller already holds. Like `acquire_buffer_lock`,
+    /// another user's expired lock needs confirming through `confirm_steal`
+    /// when `Config::confirm_expired_steals` is set, and the lock is refused
+    /// with `Error::UserContentQuotaExceeded` past the user's content quota.
+    pub async fn acquire_if_version(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        expected_version: i64,
+    ) -> Result<bool> {
+        self.metrics.record_attempt(buffer_id);
+        self.shed_if_overloaded(buffer_id, user_id).await?;
+
+        let mut tx = self.pool.begin().await?;
+        self.lock_content_quota(&mut *tx, user_id).await?;
+
+        let acquired = sqlx::query!(
+            "UPDATE buffers
+             SET locked_by = $1,
+                 locked_at = COALESCE($6, NOW()),
+                 lock_count = CASE
+                     WHEN locked_by = $1 AND NOT buffer_lock_expired(locked_at, $6, $4)
+                     THEN lock_count + 1
+                     ELSE 1
+                 END,
+                 lock_intent = CASE
+                     WHEN locked_by = $1 AND NOT buffer_lock_expired(locked_at, $6, $4)
+                     THEN lock_intent
+                     ELSE NULL
+                 END,
+                 lease_token = CASE
+                     WHEN locked_by = $1 AND NOT buffer_lock_expired(locked_at, $6, $4)
+                     THEN lease_token
+                     ELSE NULL
+                 END,
+                 last_holder = $1,
+                 deleted_at = NULL
+             WHERE id = $2
+               AND (locked_by IS NULL
+                    OR locked_by = $1
+                    OR (buffer_lock_expired(locked_at, $6, $4)
+                        AND NOT $7))
+               AND version = $3
+               AND (deleted_at IS NULL OR $5)
+               AND NOT ($8::bigint IS NOT NULL
+                        AND COALESCE(octet_length(content), 0) + (
+                            SELECT COALESCE(SUM(octet_length(held.content)), 0)
+                            FROM buffers held
+                            WHERE held.locked_by = $1
+                              AND held.id <> $2
+                              AND NOT buffer_lock_expired(held.locked_at, $6, $4)
+                        ) > $8)
              RETURNING id",
-            buffer_id.0
+            user_id.0,
+            buffer_id.0,
+            expected_version,
+            self.config.lock_expiry.as_millis() as i64,
+            self.config.restore_on_acquire,
+            self.clock_now(),
+            self.config.confirm_expired_steals,
+            self.config.max_locked_content_per_user.map(|bytes| bytes as i64)
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if acquired.is_some() {
+            tx.commit().await?;
+            self.record_lock_change(buffer_id, user_id, AuditAction::Acquire).await;
+            return Ok(true);
+        }
+
+        // The update matched nothing; report a stale version over contention
+        let current = sqlx::query!(
+            r#"SELECT version, locked_by as "locked_by: UserId",
+                      deleted_at IS NOT NULL as "deleted!",
+                      COALESCE(buffer_lock_expired(locked_at, $3, $2), false)
+                          as "expired!",
+                      COALESCE($5::bigint IS NOT NULL
+                               AND COALESCE(octet_length(content), 0) + (
+                                   SELECT COALESCE(SUM(octet_length(held.content)), 0)
+                                   FROM buffers held
+                                   WHERE held.locked_by = $4
+                                     AND held.id <> $1
+                                     AND NOT buffer_lock_expired(held.locked_at, $3, $2)
+                               ) > $5, false) as "over_quota!"
+               FROM buffers WHERE id = $1"#,
+            buffer_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            self.clock_now(),
+            user_id.0,
+            self.config.max_locked_content_per_user.map(|bytes| bytes as i64)
+        )
+        .fetch_optional(&mut *tx)
         .await?;
 
-        Ok(result.is_some())
+        match current {
+            None => Err(Error::NotFound),
+            Some(row) if row.version != expected_version => Err(Error::VersionConflict),
+            Some(row) if row.deleted && !self.config.restore_on_acquire => {
+                Err(Error::BufferDeleted)
+            }
+            Some(row) if row.expired && self.config.confirm_expired_steals => {
+                match row.locked_by {
+                    Some(held_by) => Err(Error::ExpiredLockNeedsConfirmation { held_by }),
+                    None => Err(Error::BufferLocked { buffer_id, held_by: None }),
+                }
+            }
+            // Only a lock the update could otherwise have taken is refused for the quota
+            Some(row)
+                if row.over_quota
+                    && (row.expired || row.locked_by.is_none_or(|holder| holder == user_id)) =>
+            {
+                Err(Error::UserContentQuotaExceeded)
+            }
+            Some(row) => {
+                self.metrics.record_contention(buffer_id);
+                Err(Error::BufferLocked {
+                    buffer_id,
+                    held_by: row.locked_by,
+                })
+            }
+        }
     }
 
-    pub async fn release_buffer_lock(
+    /// Acquires a lock and returns the buffer's content in the same statement
+    ///
+    /// Returns `(false, None)` instead of an error when someone else holds the
+    /// lock, so content is only ever handed to the lock holder. Another user's
+    /// expired lock that needs confirming still fails with
+    /// `Error::ExpiredLockNeedsConfirmation`, and going over the user's content
+    /// quota with `Error::UserContentQuotaExceeded`.
+    pub async fn acquire_and_read(
         &self,
         buffer_id: BufferId,
         user_id: UserId,
-    ) -> Result<()> {
-        sqlx::query!(
-            "UPDATE buffers SET locked_by = NULL, locked_at = NULL
-             WHERE id = $1 AND locked_by = $2",
+    ) -> Result<(bool, Option<Vec<u8>>)> {
+        self.metrics.record_attempt(buffer_id);
+        self.shed_if_overloaded(buffer_id, user_id).await?;
+
+        let mut tx = self.pool.begin().await?;
+        self.lock_content_quota(&mut *tx, user_id).await?;
+
+        let acquired = sqlx::query!(
+            "UPDATE buffers
+             SET locked_by = $2,
+                 locked_at = COALESCE($5, NOW()),
+                 lock_count = CASE
+                     WHEN locked_by = $2 AND NOT buffer_lock_expired(locked_at, $5, $3)
+                     THEN lock_count + 1
+                     ELSE 1
+                 END,
+                 lock_intent = CASE
+                     WHEN locked_by = $2 AND NOT buffer_lock_expired(locked_at, $5, $3)
+                     THEN lock_intent
+                     ELSE NULL
+                 END,
+                 lease_token = CASE
+                     WHEN locked_by = $2 AND NOT buffer_lock_expired(locked_at, $5, $3)
+                     THEN lease_token
+                     ELSE NULL
+                 END,
+                 last_holder = $2,
+                 deleted_at = NULL
+             WHERE id = $1
+               AND (locked_by IS NULL
+                    OR locked_by = $2
+                    OR (buffer_lock_expired(locked_at, $5, $3)
+                        AND NOT $6))
+               AND (deleted_at IS NULL OR $4)
+               AND NOT ($7::bigint IS NOT NULL
+                        AND COALESCE(octet_length(content), 0) + (
+                            SELECT COALESCE(SUM(octet_length(held.content)), 0)
+                            FROM buffers held
+                            WHERE held.locked_by = $2
+                              AND held.id <> $1
+                              AND NOT buffer_lock_expired(held.locked_at, $5, $3)
+                        ) > $7)
+             RETURNING content",
             buffer_id.0,
-            user_id.0
+            user_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            self.config.restore_on_acquire,
+            self.clock_now(),
+            self.config.confirm_expired_steals,
+            self.config.max_locked_content_per_user.map(|bytes| bytes as i64)
         )
-        .execute(&self.pool)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(row) = acquired else {
+            let current = sqlx::query!(
+                r#"SELECT locked_by as "locked_by: UserId",
+                          deleted_at IS NOT NULL as "deleted!",
+                          COALESCE(buffer_lock_expired(locked_at, $3, $2), false)
+                              as "expired!",
+                          COALESCE($5::bigint IS NOT NULL
+                                   AND COALESCE(octet_length(content), 0) + (
+                                       SELECT COALESCE(SUM(octet_length(held.content)), 0)
+                                       FROM buffers held
+                                       WHERE held.locked_by = $4
+                                         AND held.id <> $1
+                                         AND NOT buffer_lock_expired(held.locked_at, $3, $2)
+                                   ) > $5, false) as "over_quota!"
+                   FROM buffers WHERE id = $1"#,
+                buffer_id.0,
+                self.config.lock_expiry.as_millis() as i64,
+                self.clock_now(),
+                user_id.0,
+                self.config.max_locked_content_per_user.map(|bytes| bytes as i64)
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::NotFound)?;
+            if current.deleted && !self.config.restore_on_acquire {
+                return Err(Error::BufferDeleted);
+            }
+            if let (true, true, Some(held_by)) =
+                (current.expired, self.config.confirm_expired_steals, current.locked_by)
+            {
+                return Err(Error::ExpiredLockNeedsConfirmation { held_by });
+            }
+            if current.over_quota
+                && (current.expired || current.locked_by.is_none_or(|holder| holder == user_id))
+            {
+                return Err(Error::UserContentQuotaExceeded);
+            }
+            self.metrics.record_contention(buffer_id);
+            return Ok((false, None));
+        };
+
+        tx.commit().await?;
+        self.record_lock_change(buffer_id, user_id, AuditAction::Acquire).await;
+
+        Ok((true, row.content))
+    }
+
+    /// Acquires a lock, retrying while the buffer is held until `timeout` elapses
+    ///
+    /// Contended callers join the buffer's wait queue and are granted the lock
+    /// in arrival order. Plain `acquire_buffer_lock` calls don't consult the
+    /// queue and can still get in first. Callers without a timeout of their
+    /// own can use `acquire_buffer_lock_waiting_default`.
+    pub async fn acquire_buffer_lock_waiting(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        timeout: Duration,
+    ) -> Result<bool> {
+        self.acquire_buffer_lock_until(buffer_id, user_id, Instant::now() + timeout)
+            .await
+    }
+
+    /// Like `acquire_buffer_lock_waiting`, waiting up to `Config::lock_timeout`
+    pub async fn acquire_buffer_lock_waiting_default(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        self.acquire_buffer_lock_waiting(buffer_id, user_id, self.config.lock_timeout)
+            .await
+    }
+
+    /// Acquires a lock, retrying while the buffer is held until `deadline`
+    ///
+    /// A deadline already in the past still gets one attempt if nobody is
+    /// queued for the buffer.
+    pub async fn acquire_buffer_lock_until(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        deadline: Instant,
+    ) -> Result<bool> {
+        let options = WaitOptions {
+            deadline,
+            max_attempts: self.config.max_lock_attempts,
+            lock: LockOptions::default(),
+            cancel: None,
+        };
+        self.acquire_waiting(buffer_id, user_id, options).await
+    }
+
+    pub(super) async fn acquire_waiting(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        options: WaitOptions<'_>,
+    ) -> Result<bool> {
+        let mut attempts = 0;
+
+        if self.waiter_count(buffer_id).await? == 0 {
+            attempts += 1;
+            match self
+                .acquire_buffer_lock_with(buffer_id, user_id, options.lock)
+                .await
+            {
+                Err(Error::BufferLocked { .. }) => {}
+                result => return result,
+            }
+        }
+
+        if options.cancel.as_ref().is_some_and(|cancel| cancel.is_cancelled()) {
+            return Err(Error::Cancelled);
+        }
+
+        // The entry outlives the wait by a poll so a slow last attempt still counts as queued
+        let expires_in = options.deadline.saturating_duration_since(Instant::now()) + LOCK_POLL_INTERVAL;
+        let waiter = self
+            .enqueue_waiter(buffer_id, user_id, self.config.max_waiters_per_buffer, expires_in)
+            .await?
+            .ok_or(Error::WaitQueueFull)?;
+        let enqueued_at = Instant::now();
+        let result = self
+            .wait_in_queue(waiter.id(), buffer_id, user_id, &options, attempts)
+            .await;
+        // The lock may already be held; failing here would leak it until expiry
+        if let Err(error) = waiter.dequeue().await {
+            tracing::warn!(buffer_id = buffer_id.0, %error, "failed to leave buffer wait queue");
+        }
+
+        if result.is_ok() {
+            self.metrics.record_wait_time(enqueued_at.elapsed());
+        }
+
+        result
+    }
+
+    async fn wait_in_queue(
+        &self,
+        waiter: WaiterId,
+        buffer_id: BufferId,
+        user_id: UserId,
+        options: &WaitOptions<'_>,
+        mut attempts: u32,
+    ) -> Result<bool> {
+        loop {
+            let attempts_exhausted = options.max_attempts.is_some_and(|max| attempts >= max);
+            if attempts_exhausted || Instant::now() >= options.deadline {
+                return Err(Error::BufferLockTimeout);
+            }
+            let next_poll = (Instant::now() + LOCK_POLL_INTERVAL).min(options.deadline);
+            match &options.cancel {
+                Some(cancel) => tokio::select! {
+                    _ = cancel.cancelled() => return Err(Error::Cancelled),
+                    _ = tokio::time::sleep_until(next_poll) => {}
+                },
+                None => tokio::time::sleep_until(next_poll).await,
+            }
+
+            // Every poll counts toward the attempt limit, even while queued behind others
+            attempts += 1;
+            if !self.is_next_waiter(buffer_id, waiter).await? {
+                continue;
+            }
+            match self
+                .acquire_buffer_lock_with(buffer_id, user_id, options.lock)
+                .await
+            {
+                Err(Error::BufferLocked { .. }) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Waits until `user_id` no longer holds `buffer_id`
+    ///
+    /// Returns `false` if the user still holds the lock once `timeout` elapses.
+    pub async fn wait_for_release_by(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        timeout: Duration,
+    ) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let held = sqlx::query_scalar!(
+                r#"SELECT COALESCE(locked_by = $2, false) as "held!" FROM buffers WHERE id = $1"#,
+                buffer_id.0,
+                user_id.0
+            )
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(Error::NotFound)?;
+
+            if !held {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            tokio::time::sleep_until((Instant::now() + LOCK_POLL_INTERVAL).min(deadline)).await;
+        }
+    }
+
+    /// Rejects new acquisitions while too many buffers are already locked
+    ///
+    /// Only live locks count; expired ones are free to be taken over. A caller
+    /// nesting onto a lock it already holds isn't adding one, so it is let through.
+    async fn shed_if_overloaded(&self, buffer_id: BufferId, user_id: UserId) -> Result<()> {
+        let Config {
+            overload_threshold,
+            max_global_locks,
+            ..
+        } = self.config;
+        if overload_threshold.is_none() && max_global_locks.is_none() {
+            return Ok(());
+        }
+
+        // A pre-check, not part of the claim, so concurrent acquisitions can
+        // briefly overshoot either limit by however many are in flight
+        let load = sqlx::query!(
+            r#"SELECT COUNT(*) as "locked!",
+                      COALESCE(bool_or(id = $1 AND locked_by = $2), false) as "nested!"
+               FROM buffers
+               WHERE locked_by IS NOT NULL
+                 AND NOT buffer_lock_expired(locked_at, $4, $3)"#,
+            buffer_id.0,
+            user_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            self.clock_now()
+        )
+        .fetch_one(&self.pool)
         .await?;
+        if load.nested {
+            return Ok(());
+        }
+
+        let locked = load.locked;
+        if max_global_locks.is_some_and(|max| locked >= max) {
+            return Err(Error::GlobalLockLimitReached);
+        }
+        if overload_threshold.is_some_and(|threshold| locked > threshold) {
+            return Err(Error::Overloaded);
+        }
+
+        Ok(())
+    }
+
+    /// Claims the buffer for `user_id`, nesting if they already hold it
+    ///
+    /// A lock older than the configured expiry is taken over as if it were free.
+    async fn try_acquire_lock(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        options: LockOptions<'_>,
+    ) -> Result<Claim> {
+        if self.config.max_locked_content_per_user.is_none() {
+            let mut conn = self.pool.acquire().await?;
+            return self.claim_lock(&mut *conn, buffer_id, user_id, options).await;
+        }
+
+        // The quota's advisory lock is only held for the length of a transaction
+        let mut tx = self.pool.begin().await?;
+        let claim = self.claim_lock(&mut *tx, buffer_id, user_id, options).await?;
+        tx.commit().await?;
+
+        Ok(claim)
+    }
+
+    /// Serializes everything that puts buffers under `user_id`'s lock while a
+    /// content quota is configured
+    ///
+    /// Claims by the same user on different buffers don't touch the same row,
+    /// so without this both could sum the other's content as unlocked and pass
+    /// the quota together. Held until the transaction on `conn` ends.
+    async fn lock_content_quota(&self, conn: &mut PgConnection, user_id: UserId) -> Result<()> {
+        if self.config.max_locked_content_per_user.is_some() {
+            sqlx::query!("SELECT 1 as locked FROM pg_advisory_xact_lock($1)", user_id.0)
+                .execute(&mut *conn)
+                .await?;
+        }
 
         Ok(())
     }
+
+    /// Runs the claim on `conn`, which must be inside a transaction when a
+    /// content quota is configured
+    async fn claim_lock(
+        &self,
+        conn: &mut PgConnection,
+        buffer_id: BufferId,
+        user_id: UserId,
+        options: LockOptions<'_>,
+    ) -> Result<Claim> {
+        self.lock_content_quota(&mut *conn, user_id).await?;
+
+        // `previous` reads the row as it was before the update, so a rejected
+        // claim still learns who held the buffer in the same statement. The
+        // quota counts the content of the user's other live locks plus this one.
+        let claim = sqlx::query_as!(
+            Claim,
+            r#"WITH previous AS (
+                   SELECT locked_by,
+                          buffer_lock_expired(locked_at, $8, $3) as expired,
+                          deleted_at IS NOT NULL as deleted,
+                          $6::bigint IS NOT NULL
+                              AND (locked_by IS NULL
+                                   OR locked_by = $2
+                                   OR (buffer_lock_expired(locked_at, $8, $3)
+                                       AND NOT $9))
+                              AND COALESCE(octet_length(content), 0) + (
+                                  SELECT COALESCE(SUM(octet_length(held.content)), 0)
+                                  FROM buffers held
+                                  WHERE held.locked_by = $2
+                                    AND held.id <> $1
+                                    AND NOT buffer_lock_expired(held.locked_at, $8, $3)
+                              ) > $6 as over_quota
+                   FROM buffers WHERE id = $1
+               ),
+               claimed AS (
+                   UPDATE buffers
+                   SET locked_by = $2,
+                       locked_at = COALESCE($8, NOW()),
+                       lock_count = CASE
+                           WHEN locked_by = $2 AND NOT buffer_lock_expired(locked_at, $8, $3)
+                           THEN lock_count + 1
+                           ELSE 1
+                       END,
+                       lock_intent = CASE
+                           WHEN locked_by = $2 AND NOT buffer_lock_expired(locked_at, $8, $3)
+                           THEN COALESCE($4, lock_intent)
+                           ELSE $4
+                       END,
+                       lease_token = CASE
+                           WHEN locked_by = $2 AND NOT buffer_lock_expired(locked_at, $8, $3)
+                           THEN COALESCE(lease_token, $7)
+                           ELSE $7
+                       END,
+                       last_holder = $2,
+                       last_activity_at = CASE WHEN $10 THEN COALESCE($8, NOW()) ELSE last_activity_at END,
+                       last_activity_by = CASE WHEN $10 THEN $2 ELSE last_activity_by END,
+                       deleted_at = NULL
+                   WHERE id = $1
+                     AND (locked_by IS NULL
+                          OR locked_by = $2
+                          OR (buffer_lock_expired(locked_at, $8, $3)
+                              AND NOT $9))
+                     AND (deleted_at IS NULL OR $5)
+                     AND NOT COALESCE((SELECT over_quota FROM previous), false)
+                   RETURNING lease_token
+               )
+               SELECT EXISTS(SELECT 1 FROM previous) as "exists!",
+                      EXISTS(SELECT 1 FROM claimed) as "acquired!",
+                      (SELECT locked_by FROM previous) as "held_by: UserId",
+                      COALESCE((SELECT expired FROM previous), false) as "held_expired!",
+                      COALESCE((SELECT deleted FROM previous), false) AND NOT $5 as "deleted!",
+                      COALESCE((SELECT over_quota FROM previous), false) as "over_quota!",
+                      $9 as "steal_needs_confirmation!",
+                      (SELECT lease_token FROM claimed) as "lease_token: LeaseToken""#,
+            buffer_id.0,
+            user_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            options.intent,
+            self.config.restore_on_acquire,
+            self.config.max_locked_content_per_user.map(|bytes| bytes as i64),
+            LeaseToken::generate().0,
+            self.clock_now(),
+            self.config.confirm_expired_steals && !options.confirm_steal,
+            options.touch_activity
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(claim)
+    }
+
+    /// Acquires a lock and returns the lease token that renewals and releases must present
+    ///
+    /// Nesting onto a lock the caller already holds returns its existing
+    /// token. Leases always go to the database, even while degraded.
+    pub async fn acquire_lease(&self, buffer_id: BufferId, user_id: UserId) -> Result<LeaseToken> {
+        self.metrics.record_attempt(buffer_id);
+        self.shed_if_overloaded(buffer_id, user_id).await?;
+
+        let claim = self
+            .try_acquire_lock(buffer_id, user_id, LockOptions::default())
+            .await?;
+        if let Err(error) = claim.ensure_acquired(buffer_id) {
+            if matches!(error, Error::BufferLocked { .. }) {
+                self.metrics.record_contention(buffer_id);
+            }
+            return Err(error);
+        }
+
+        self.record_lock_change(buffer_id, user_id, AuditAction::Acquire).await;
+
+        claim.lease_token.ok_or(Error::StaleLease)
+    }
+
+    /// Extends a lock acquired with `acquire_lease`, if `token` is still its lease
+    ///
+    /// Fails with `Error::StaleLease` if the lock has been granted again since
+    /// `token` was issued, even to the same user.
+    pub async fn renew_lease(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        token: LeaseToken,
+    ) -> Result<()> {
+        let renewed = sqlx::query!(
+            "UPDATE buffers SET locked_at = COALESCE($4, NOW())
+             WHERE id = $1 AND locked_by = $2 AND lease_token = $3",
+            buffer_id.0,
+            user_id.0,
+            token.0,
+            self.clock_now()
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if renewed == 0 {
+            return Err(self.lease_mismatch(buffer_id, user_id).await);
+        }
+
+        Ok(())
+    }
+
+    /// Releases a lock acquired with `acquire_lease` entirely, however deeply it was nested
+    ///
+    /// Fails with `Error::StaleLease` if `token` is no longer the lock's lease.
+    pub async fn release_lease(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        token: LeaseToken,
+    ) -> Result<()> {
+        let released = sqlx::query!(
+            r#"UPDATE buffers b
+               SET locked_by = NULL, locked_at = NULL, lock_count = 0, lock_intent = NULL,
+                   lease_token = NULL
+               FROM buffers old
+               WHERE b.id = old.id AND b.id = $1 AND b.locked_by = $2 AND b.lease_token = $3
+               RETURNING (EXTRACT(EPOCH FROM COALESCE($4, NOW()) - old.locked_at) * 1000)::bigint as held_ms"#,
+            buffer_id.0,
+            user_id.0,
+            token.0,
+            self.clock_now()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(released) = released else {
+            return Err(self.lease_mismatch(buffer_id, user_id).await);
+        };
+
+        if let Some(held_ms) = released.held_ms {
+            self.metrics.record_hold_duration(Duration::from_millis(held_ms.max(0) as u64));
+        }
+        self.record_lock_change(buffer_id, user_id, AuditAction::Release).await;
+
+        Ok(())
+    }
+
+    /// Explains why a lease operation by `user_id` matched nothing
+    async fn lease_mismatch(&self, buffer_id: BufferId, user_id: UserId) -> Error {
+        let holder = sqlx::query_scalar!(
+            r#"SELECT locked_by as "locked_by: UserId" FROM buffers WHERE id = $1"#,
+            buffer_id.0
+        )
+        .fetch_optional(&self.pool)
+        .await;
+
+        match holder {
+            Err(error) => Error::Database(error),
+            Ok(None) => Error::NotFound,
+            Ok(Some(holder)) if holder == Some(user_id) => Error::StaleLease,
+            Ok(Some(_)) => Error::NotLockHolder,
+        }
+    }
+
+    /// Extends `user_id`'s lock, or reports who holds the buffer now if they lost it
+    ///
+    /// Matches on the holder only and skips the lease check, so it also extends
+    /// a lock taken with `acquire_lease`; use `renew_lease` to check the token.
+    pub async fn renew_or_lost(&self, buffer_id: BufferId, user_id: UserId) -> Result<RenewResult> {
+        let renewed = sqlx::query!(
+            "UPDATE buffers SET locked_at = COALESCE($3, NOW()) WHERE id = $1 AND locked_by = $2",
+            buffer_id.0,
+            user_id.0,
+            self.clock_now()
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if renewed > 0 {
+            return Ok(RenewResult::Renewed);
+        }
+
+        let now_held_by = sqlx::query_scalar!(
+            r#"SELECT locked_by as "locked_by: UserId" FROM buffers WHERE id = $1"#,
+            buffer_id.0
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        Ok(RenewResult::Lost { now_held_by })
+    }
+
+    /// Extends every lock `user_id` holds, returning how many were renewed
+    ///
+    /// Skips the lease check, extending leased locks regardless of their token.
+    pub async fn renew_all_locks(&self, user_id: UserId) -> Result<u64> {
+        let renewed = sqlx::query!(
+            "UPDATE buffers SET locked_at = COALESCE($2, NOW()) WHERE locked_by = $1",
+            user_id.0,
+            self.clock_now()
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(renewed)
+    }
+
+    /// Locks a free or expired buffer, but only for the user who last held it
+    ///
+    /// Lets a reconnecting client take back a buffer it was editing without
+    /// racing anyone else for it. Returns `false` if the buffer is held, or if
+    /// someone else held it last, and `Error::UserContentQuotaExceeded` if
+    /// taking it back would put the user over their content quota.
+    pub async fn reacquire_if_previous(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        self.metrics.record_attempt(buffer_id);
+        self.shed_if_overloaded(buffer_id, user_id).await?;
+
+        let mut tx = self.pool.begin().await?;
+        self.lock_content_quota(&mut *tx, user_id).await?;
+
+        // `previous` reads the row before the update, so a refusal for the
+        // quota can be told apart from the buffer being unavailable
+        let reacquired = sqlx::query!(
+            r#"WITH previous AS (
+                   SELECT last_holder = $2
+                              AND (locked_by IS NULL
+                                   OR buffer_lock_expired(locked_at, $5, $3))
+                              AND (deleted_at IS NULL OR $4) as reclaimable,
+                          $6::bigint IS NOT NULL
+                              AND COALESCE(octet_length(content), 0) + (
+                                  SELECT COALESCE(SUM(octet_length(held.content)), 0)
+                                  FROM buffers held
+                                  WHERE held.locked_by = $2
+                                    AND held.id <> $1
+                                    AND NOT buffer_lock_expired(held.locked_at, $5, $3)
+                              ) > $6 as over_quota
+                   FROM buffers WHERE id = $1
+               ),
+               reclaimed AS (
+                   UPDATE buffers
+                   SET locked_by = $2, locked_at = COALESCE($5, NOW()), lock_count = 1,
+                       lock_intent = NULL, lease_token = NULL, deleted_at = NULL
+                   WHERE id = $1
+                     AND last_holder = $2
+                     AND (locked_by IS NULL OR buffer_lock_expired(locked_at, $5, $3))
+                     AND (deleted_at IS NULL OR $4)
+                     AND NOT COALESCE((SELECT over_quota FROM previous), false)
+                   RETURNING id
+               )
+               SELECT EXISTS(SELECT 1 FROM previous) as "exists!",
+                      EXISTS(SELECT 1 FROM reclaimed) as "reacquired!",
+                      COALESCE((SELECT reclaimable AND over_quota FROM previous), false) as "over_quota!""#,
+            buffer_id.0,
+            user_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            self.config.restore_on_acquire,
+            self.clock_now(),
+            self.config.max_locked_content_per_user.map(|bytes| bytes as i64)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if !reacquired.reacquired {
+            if !reacquired.exists {
+                return Err(Error::NotFound);
+            }
+            if reacquired.over_quota {
+                return Err(Error::UserContentQuotaExceeded);
+            }
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+        self.record_lock_change(buffer_id, user_id, AuditAction::Acquire).await;
+
+        Ok(true)
+    }
+
+    /// Hands a lock from `from` to `to` and bumps the buffer version in one statement
+    ///
+    /// Returns the new version, or `Error::NotLockHolder` if `from` doesn't hold
+    /// the lock. Fails with `Error::UserContentQuotaExceeded`, leaving the lock
+    /// with `from`, if `to` would end up over their content quota.
+    pub async fn transfer_and_bump(
+        &self,
+        buffer_id: BufferId,
+        from: UserId,
+        to: UserId,
+    ) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        self.lock_content_quota(&mut *tx, to).await?;
+
+        let transfer = sqlx::query!(
+            r#"WITH previous AS (
+                   SELECT $5::bigint IS NOT NULL
+                              AND COALESCE(octet_length(content), 0) + (
+                                  SELECT COALESCE(SUM(octet_length(held.content)), 0)
+                                  FROM buffers held
+                                  WHERE held.locked_by = $3
+                                    AND held.id <> $1
+                                    AND NOT buffer_lock_expired(held.locked_at, $4, $6)
+                              ) > $5 as over_quota
+                   FROM buffers WHERE id = $1 AND locked_by = $2
+               ),
+               transferred AS (
+                   UPDATE buffers
+                   SET locked_by = $3, locked_at = COALESCE($4, NOW()), lock_count = 1,
+                       lock_intent = NULL, last_holder = $3, lease_token = NULL, version = version + 1
+                   WHERE id = $1 AND locked_by = $2
+                     AND NOT COALESCE((SELECT over_quota FROM previous), false)
+                   RETURNING version
+               )
+               SELECT (SELECT version FROM transferred) as version,
+                      COALESCE((SELECT over_quota FROM previous), false) as "over_quota!""#,
+            buffer_id.0,
+            from.0,
+            to.0,
+            self.clock_now(),
+            self.config.max_locked_content_per_user.map(|bytes| bytes as i64),
+            self.config.lock_expiry.as_millis() as i64
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if transfer.over_quota {
+            return Err(Error::UserContentQuotaExceeded);
+        }
+        let version = transfer.version.ok_or(Error::NotLockHolder)?;
+        tx.commit().await?;
+
+        self.record_lock_change(buffer_id, from, AuditAction::Release).await;
+        self.record_lock_change(buffer_id, to, AuditAction::Acquire).await;
+
+        Ok(version as u64)
+    }
+
+    /// Hands every lock in `buffer_ids` held by `from` over to `to`
+    ///
+    /// Buffers `from` doesn't hold are left alone. Returns how many moved. The
+    /// move is all or nothing: if the content `to` would end up holding is over
+    /// their quota, nothing moves and `Error::UserContentQuotaExceeded` is returned.
+    pub async fn transfer_locks(
+        &self,
+        buffer_ids: &[BufferId],
+        from: UserId,
+        to: UserId,
+    ) -> Result<u64> {
+        let ids: Vec<i64> = buffer_ids.iter().map(|id| id.0).collect();
+        let mut tx = self.pool.begin().await?;
+        self.lock_content_quota(&mut *tx, to).await?;
+
+        // Locking the rows keeps the set being moved, and so the content
+        // counted against `to`, fixed until the update
+        let moving = sqlx::query!(
+            r#"SELECT id as "id: BufferId", COALESCE(octet_length(content), 0) as "bytes!"
+               FROM buffers WHERE id = ANY($1) AND locked_by = $2
+               FOR UPDATE"#,
+            &ids,
+            from.0
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let moving_ids: Vec<i64> = moving.iter().map(|row| row.id.0).collect();
+
+        if let Some(quota) = self.config.max_locked_content_per_user {
+            let held = sqlx::query_scalar!(
+                r#"SELECT COALESCE(SUM(octet_length(content)), 0)::bigint as "bytes!"
+                   FROM buffers
+                   WHERE locked_by = $1
+                     AND id <> ALL($2)
+                     AND NOT buffer_lock_expired(locked_at, $3, $4)"#,
+                to.0,
+                &moving_ids,
+                self.clock_now(),
+                self.config.lock_expiry.as_millis() as i64
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            let moving_bytes: i64 = moving.iter().map(|row| i64::from(row.bytes)).sum();
+            if held + moving_bytes > quota as i64 {
+                return Err(Error::UserContentQuotaExceeded);
+            }
+        }
+
+        let transferred = sqlx::query_scalar!(
+            r#"UPDATE buffers
+               SET locked_by = $3, locked_at = COALESCE($4, NOW()), lock_count = 1,
+                   lock_intent = NULL, last_holder = $3, lease_token = NULL
+               WHERE id = ANY($1) AND locked_by = $2
+               RETURNING id as "id: BufferId""#,
+            &moving_ids,
+            from.0,
+            to.0,
+            self.clock_now()
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        for &buffer_id in &transferred {
+            self.record_lock_change(buffer_id, from, AuditAction::Release).await;
+            self.record_lock_change(buffer_id, to, AuditAction::Acquire).await;
+        }
+
+        Ok(transferred.len() as u64)
+    }
+
+    /// Releases one level of `user_id`'s lock on a buffer
+    ///
+    /// Returns `Ok(false)` if the buffer exists but the user didn't hold it, and
+    /// `Error::NotFound` if there is no such buffer. Skips the lease check, so
+    /// it also releases a lock taken with `acquire_lease`; use `release_lease`
+    /// to check the token.
+    pub async fn release_buffer_lock(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        self.release_buffer_lock_with_correlation_id(buffer_id, user_id, None)
+            .await
+    }
+
+    /// Releases one level of a lock, tagging the span and audit entry with `correlation_id`
+    ///
+    /// Like `release_buffer_lock`, it skips the lease check.
+    pub async fn release_buffer_lock_with_correlation_id(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        correlation_id: Option<&str>,
+    ) -> Result<bool> {
+        let span = tracing::info_span!(
+            "release_buffer_lock",
+            buffer_id = buffer_id.0,
+            user_id = user_id.0,
+            correlation_id
+        );
+        self.unlock_buffer(buffer_id, user_id, correlation_id)
+            .instrument(span)
+            .await
+    }
+
+    async fn unlock_buffer(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        correlation_id: Option<&str>,
+    ) -> Result<bool> {
+        // Locks granted while degraded live only in memory until reconciled;
+        // anything else was taken in the database and must be released there,
+        // outage or not
+        if let Some(fallback) = &self.fallback {
+            if fallback.release(buffer_id, user_id) {
+                return Ok(true);
+            }
+        }
+
+        // Nested acquisitions by the same user only unwind one level
+        let released = sqlx::query!(
+            r#"UPDATE buffers b
+               SET lock_count = GREATEST(b.lock_count - 1, 0),
+                   locked_by = CASE WHEN b.lock_count > 1 THEN b.locked_by END,
+                   locked_at = CASE WHEN b.lock_count > 1 THEN b.locked_at END,
+                   lock_intent = CASE WHEN b.lock_count > 1 THEN b.lock_intent END,
+                   lease_token = CASE WHEN b.lock_count > 1 THEN b.lease_token END
+               FROM buffers old
+               WHERE b.id = old.id AND b.id = $1 AND b.locked_by = $2
+               RETURNING b.locked_by IS NULL as "fully_released!",
+                         (EXTRACT(EPOCH FROM COALESCE($3, NOW()) - old.locked_at) * 1000)::bigint as held_ms"#,
+            buffer_id.0,
+            user_id.0,
+            self.clock_now()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(released) = released else {
+            let exists = self.buffer_exists(buffer_id).await?;
+            return if exists { Ok(false) } else { Err(Error::NotFound) };
+        };
+
+        if released.fully_released {
+            // A missing locked_at means the row was inconsistent; don't record a made-up duration
+            if let Some(held_ms) = released.held_ms {
+                self.metrics.record_hold_duration(Duration::from_millis(held_ms.max(0) as u64));
+            }
+            let context = AuditContext {
+                correlation_id,
+                ..AuditContext::default()
+            };
+            self.record_lock_change_with(buffer_id, user_id, AuditAction::Release, context).await;
+        }
+
+        Ok(true)
+    }
+
+    /// Moves `user_id`'s lock from one buffer to another in a single transaction
+    ///
+    /// The old lock is dropped entirely, however deeply it was nested. If the new
+    /// buffer can't be locked nothing changes and `Error::BufferLocked` is returned.
+    pub async fn release_and_acquire(
+        &self,
+        release_buffer: BufferId,
+        acquire_buffer: BufferId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        self.metrics.record_attempt(acquire_buffer);
+        let mut tx = self.pool.begin().await?;
+
+        let released_held_ms = sqlx::query_scalar!(
+            r#"UPDATE buffers b
+               SET locked_by = NULL, locked_at = NULL, lock_count = 0, lock_intent = NULL,
+                   lease_token = NULL
+               FROM buffers old
+               WHERE b.id = old.id AND b.id = $1 AND b.locked_by = $2
+               RETURNING (EXTRACT(EPOCH FROM COALESCE($3, NOW()) - old.locked_at) * 1000)::bigint as held_ms"#,
+            release_buffer.0,
+            user_id.0,
+            self.clock_now()
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let claim = self
+            .claim_lock(&mut *tx, acquire_buffer, user_id, LockOptions::default())
+            .await?;
+        if let Err(error) = claim.ensure_acquired(acquire_buffer) {
+            if matches!(error, Error::BufferLocked { .. }) {
+                self.metrics.record_contention(acquire_buffer);
+            }
+            return Err(error);
+        }
+
+        tx.commit().await?;
+
+        if let Some(held_ms) = released_held_ms {
+            if let Some(held_ms) = held_ms {
+                self.metrics.record_hold_duration(Duration::from_millis(held_ms.max(0) as u64));
+            }
+            self.record_lock_change(release_buffer, user_id, AuditAction::Release).await;
+        }
+        self.record_lock_change(acquire_buffer, user_id, AuditAction::Acquire).await;
+
+        Ok(true)
+    }
+
+    /// Marks a held lock as expired so the next acquisition or reap reclaims it
+    ///
+    /// Unlike a release, the holder is left in place until someone takes over.
+    /// Returns whether the buffer was locked.
+    pub async fn expire_lock_now(&self, buffer_id: BufferId) -> Result<bool> {
+        let expired = sqlx::query!(
+            "UPDATE buffers SET locked_at = TIMESTAMPTZ 'epoch'
+             WHERE id = $1 AND locked_by IS NOT NULL",
+            buffer_id.0
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(expired > 0)
+    }
+
+    /// Releases every lock older than the configured expiry, returning how many were freed
+    ///
+    /// Does nothing while `Config::confirm_expired_steals` is set, since freeing
+    /// an expired lock would let anyone take it without confirming.
+    pub async fn reap_expired_locks(&self) -> Result<u64> {
+        if self.config.confirm_expired_steals {
+            return Ok(0);
+        }
+        let reaped = self.release_locks_older_than(self.config.lock_expiry).await?;
+        Ok(reaped.len() as u64)
+    }
+
+    /// Releases every lock held for longer than `age`, returning the freed buffers
+    ///
+    /// Meant as an explicit admin sweep; unlike expiry it applies whatever
+    /// `age` is given, however recent.
+    pub async fn release_locks_older_than(&self, age: Duration) -> Result<Vec<BufferId>> {
+        let released = sqlx::query!(
+            r#"UPDATE buffers b
+               SET locked_by = NULL, locked_at = NULL, lock_count = 0, lock_intent = NULL,
+                   lease_token = NULL
+               FROM buffers old
+               WHERE b.id = old.id
+                 AND b.locked_by IS NOT NULL
+                 AND buffer_lock_expired(b.locked_at, $2, $1)
+               RETURNING old.id as "buffer_id: BufferId", old.locked_by as "user_id!: UserId""#,
+            age.as_millis() as i64,
+            self.clock_now()
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for lock in &released {
+            self.record_lock_change(lock.buffer_id, lock.user_id, AuditAction::Release).await;
+        }
+
+        Ok(released.into_iter().map(|lock| lock.buffer_id).collect())
+    }
+
+    /// Releases every lock whose holder has no connection in `registry`
+    ///
+    /// Assumes this process serves every client: locks held by users connected
+    /// to another process look orphaned here and are released too. Returns how
+    /// many locks were freed.
+    pub async fn reconcile(&self, registry: &ConnectionRegistry) -> Result<u64> {
+        let connected: Vec<i64> = registry
+            .connected_users()
+            .into_iter()
+            .map(|user_id| user_id.0)
+            .collect();
+        let released = sqlx::query!(
+            r#"UPDATE buffers b
+               SET locked_by = NULL, locked_at = NULL, lock_count = 0, lock_intent = NULL,
+                   lease_token = NULL
+               FROM buffers old
+               WHERE b.id = old.id
+                 AND b.locked_by IS NOT NULL
+                 AND b.locked_by <> ALL($1)
+               RETURNING old.id as "buffer_id: BufferId", old.locked_by as "user_id!: UserId""#,
+            &connected
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for lock in &released {
+            self.record_lock_change(lock.buffer_id, lock.user_id, AuditAction::Release).await;
+        }
+
+        Ok(released.len() as u64)
+    }
+
+    /// Releases every lock held by one of `user_ids`, however deeply nested, returning the freed buffers
+    pub async fn release_locks_held_by(&self, user_ids: &[UserId]) -> Result<Vec<BufferId>> {
+        let ids: Vec<i64> = user_ids.iter().map(|user_id| user_id.0).collect();
+        let released = sqlx::query!(
+            r#"UPDATE buffers b
+               SET locked_by = NULL, locked_at = NULL, lock_count = 0, lock_intent = NULL,
+                   lease_token = NULL
+               FROM buffers old
+               WHERE b.id = old.id
+                 AND b.locked_by = ANY($1)
+               RETURNING old.id as "buffer_id: BufferId", old.locked_by as "user_id!: UserId""#,
+            &ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for lock in &released {
+            self.record_lock_change(lock.buffer_id, lock.user_id, AuditAction::Release).await;
+        }
+
+        Ok(released.into_iter().map(|lock| lock.buffer_id).collect())
+    }
+
+    /// Returns buffers whose `locked_by` and `locked_at` disagree about being locked
+    ///
+    /// Every lock path sets or clears both together, so any row found here
+    /// points at a bug or a manual edit.
+    pub async fn find_inconsistent_locks(&self) -> Result<Vec<BufferId>> {
+        let buffer_ids = sqlx::query_scalar!(
+            r#"SELECT id as "id: BufferId"
+               FROM buffers
+               WHERE (locked_by IS NULL) <> (locked_at IS NULL)
+               ORDER BY id ASC"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(buffer_ids)
+    }
+
+    /// Clears the lock on every buffer `find_inconsistent_locks` would report
+    pub async fn repair_inconsistent_locks(&self) -> Result<Vec<BufferId>> {
+        let repaired = sqlx::query_scalar!(
+            r#"UPDATE buffers
+               SET locked_by = NULL, locked_at = NULL, lock_count = 0, lock_intent = NULL,
+                   lease_token = NULL
+               WHERE (locked_by IS NULL) <> (locked_at IS NULL)
+               RETURNING id as "id: BufferId""#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for buffer_id in &repaired {
+            tracing::warn!(buffer_id = buffer_id.0, "cleared inconsistent buffer lock");
+        }
+
+        Ok(repaired)
+    }
+
+    /// Rough chance that an acquisition of `buffer_id` would be rejected right now
+    ///
+    /// This is a heuristic for UI hints, not a prediction: a buffer with a live
+    /// lock scores 1.0, otherwise the score is the share of this process's
+    /// recent attempts on it that hit contention.
+    pub async fn estimated_wait_probability(&self, buffer_id: BufferId) -> Result<f64> {
+        let held = sqlx::query_scalar!(
+            r#"SELECT (locked_by IS NOT NULL
+                       AND NOT buffer_lock_expired(locked_at, $3, $2)) as "held!"
+               FROM buffers WHERE id = $1"#,
+            buffer_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            self.clock_now()
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        if held {
+            return Ok(1.0);
+        }
+
+        Ok(self.metrics.buffer_contention_ratio(buffer_id))
+    }
+
+    /// Returns the `n` buffers with the most acquisitions rejected by contention
+    ///
+    /// Counts are kept in memory and only cover attempts made through this process.
+    pub async fn top_contended_buffers(&self, n: usize) -> Result<Vec<(BufferId, u64)>> {
+        Ok(self.metrics.top_contended(n))
+    }
+
+    /// Replaces a buffer's content only if it currently equals `expected`
+    ///
+    /// Compares the stored `content_hash` rather than the bytes themselves and
+    /// bumps the version on success. Locks are not consulted, so this is meant
+    /// for optimistic editing without holding one.
+    pub async fn cas_content(
+        &self,
+        buffer_id: BufferId,
+        expected: &[u8],
+        new: &[u8],
+    ) -> Result<bool> {
+        let swapped = sqlx::query!(
+            "UPDATE buffers
+             SET content = $3, content_hash = sha256($3), version = version + 1
+             WHERE id = $1 AND COALESCE(content_hash, sha256(content)) = sha256($2)",
+            buffer_id.0,
+            expected,
+            new
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(swapped > 0)
+    }
+
+    /// Returns the lock holder and latest activity of a buffer
+    pub async fn buffer_info(&self, buffer_id: BufferId) -> Result<BufferInfo> {
+        let pool = self.read_pool().await;
+        let info = sqlx::query_as!(
+            BufferInfo,
+            r#"SELECT id as "id: BufferId", locked_by as "locked_by: UserId", locked_at, version,
+                      last_activity_at, last_activity_by as "last_activity_by: UserId"
+               FROM buffers WHERE id = $1"#,
+            buffer_id.0
+        )
+        .fetch_optional(&pool)
+        .await?;
+
+        info.ok_or(Error::NotFound)
+    }
+
+    /// Returns the current version of each of `buffer_ids`, omitting unknown ids
+    pub async fn buffer_versions(&self, buffer_ids: &[BufferId]) -> Result<HashMap<BufferId, i64>> {
+        let ids: Vec<i64> = buffer_ids.iter().map(|id| id.0).collect();
+        let rows = sqlx::query!(
+            r#"SELECT id as "id: BufferId", version FROM buffers WHERE id = ANY($1)"#,
+            &ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.version)).collect())
+    }
+
+    /// Marks a buffer as deleted without removing its row
+    ///
+    /// Existing locks are left alone, but new acquisitions fail with
+    /// `Error::BufferDeleted` unless `Config::restore_on_acquire` is set.
+    pub async fn soft_delete_buffer(&self, buffer_id: BufferId) -> Result<()> {
+        let deleted = sqlx::query!(
+            "UPDATE buffers SET deleted_at = COALESCE(deleted_at, NOW()) WHERE id = $1",
+            buffer_id.0
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if deleted == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Clears a buffer's soft-delete tombstone
+    pub async fn restore_buffer(&self, buffer_id: BufferId) -> Result<()> {
+        let restored = sqlx::query!(
+            "UPDATE buffers SET deleted_at = NULL WHERE id = $1",
+            buffer_id.0
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if restored == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Whether a buffer row exists, regardless of its lock state
+    pub async fn buffer_exists(&self, buffer_id: BufferId) -> Result<bool> {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM buffers WHERE id = $1) as "exists!""#,
+            buffer_id.0
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    /// Returns which of `buffer_ids` `user_id` could lock right now
+    ///
+    /// A buffer qualifies if it is unlocked, its lock has expired, or `user_id`
+    /// already holds it. This is only a preview: another user may take any of
+    /// them before the caller gets to acquire. Unknown ids are left out.
+    pub async fn acquirable(
+        &self,
+        buffer_ids: &[BufferId],
+        user_id: UserId,
+    ) -> Result<Vec<BufferId>> {
+        let pool = self.read_pool().await;
+        let ids: Vec<i64> = buffer_ids.iter().map(|id| id.0).collect();
+        let acquirable = sqlx::query_scalar!(
+            r#"SELECT id as "id: BufferId"
+               FROM buffers
+               WHERE id = ANY($1)
+                 AND (locked_by IS NULL
+                      OR locked_by = $2
+                      OR buffer_lock_expired(locked_at, $4, $3))
+               ORDER BY id ASC"#,
+            &ids,
+            user_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            self.clock_now()
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(acquirable)
+    }
+
+    /// Reports for each of `buffer_ids` whether `user_id` could lock it, and who holds it if not
+    ///
+    /// Applies the same rules as `acquirable` and is just as much a preview.
+    /// Unknown ids are left out.
+    pub async fn acquirability_report(
+        &self,
+        buffer_ids: &[BufferId],
+        user_id: UserId,
+    ) -> Result<HashMap<BufferId, Acquirability>> {
+        let pool = self.read_pool().await;
+        let ids: Vec<i64> = buffer_ids.iter().map(|id| id.0).collect();
+        let rows = sqlx::query!(
+            r#"SELECT id as "id: BufferId",
+                      CASE
+                          WHEN locked_by IS NULL
+                               OR locked_by = $2
+                               OR buffer_lock_expired(locked_at, $4, $3)
+                          THEN NULL
+                          ELSE locked_by
+                      END as "held_by: UserId"
+               FROM buffers
+               WHERE id = ANY($1)"#,
+            &ids,
+            user_id.0,
+            self.config.lock_expiry.as_millis() as i64,
+            self.clock_now()
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let acquirability = match row.held_by {
+                    Some(holder) => Acquirability::HeldBy(holder),
+                    None => Acquirability::Available,
+                };
+                (row.id, acquirability)
+            })
+            .collect())
+    }
+
+    /// Returns a page of buffers in the given lock state, ordered by id
+    pub async fn list_buffers_by_state(
+        &self,
+        state: LockState,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<BufferInfo>> {
+        let pool = self.read_pool().await;
+        let buffers = sqlx::query_as!(
+            BufferInfo,
+            r#"SELECT id as "id: BufferId", locked_by as "locked_by: UserId", locked_at, version,
+                      last_activity_at, last_activity_by as "last_activity_by: UserId"
+               FROM buffers
+               WHERE CASE $1
+                   WHEN 'unlocked' THEN locked_by IS NULL
+                   WHEN 'locked' THEN locked_by IS NOT NULL
+                       AND NOT buffer_lock_expired(locked_at, $5, $2)
+                   WHEN 'expired' THEN locked_by IS NOT NULL
+                       AND buffer_lock_expired(locked_at, $5, $2)
+               END
+               ORDER BY id ASC
+               LIMIT $3 OFFSET $4"#,
+            state as LockState,
+            self.config.lock_expiry.as_millis() as i64,
+            limit,
+            offset,
+            self.clock_now()
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(buffers)
+    }
+
+    /// Records that `user_id` is looking at a buffer without locking it
+    pub async fn touch_buffer(&self, buffer_id: BufferId, user_id: UserId) -> Result<()> {
+        let touched = sqlx::query!(
+            "UPDATE buffers SET last_activity_at = COALESCE($3, NOW()), last_activity_by = $2
+             WHERE id = $1",
+            buffer_id.0,
+            user_id.0,
+            self.clock_now()
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if touched == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Returns every currently held lock, oldest first
+    pub async fn active_locks(&self) -> Result<Vec<LockRecord>> {
+        let pool = self.read_pool().await;
+        let locks = sqlx::query_as!(
+            LockRecord,
+            r#"SELECT id as "buffer_id: BufferId", locked_by as "locked_by!: UserId",
+                      locked_at as "locked_at!", lock_intent as intent
+               FROM buffers
+               WHERE locked_by IS NOT NULL
+               ORDER BY locked_at ASC, id ASC"#
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(locks)
+    }
+
+    /// Streams every currently held lock, oldest first, without buffering them all
+    ///
+    /// Holds a pool connection until the stream is dropped or exhausted.
+    /// Unlike `active_locks` it always reads the primary: `read_pool` has to
+    /// await a lag check and hands back an owned pool, which the returned
+    /// stream couldn't borrow from.
+    pub fn stream_active_locks(&self) -> impl Stream<Item = Result<LockRecord>> + '_ {
+        sqlx::query_as!(
+            LockRecord,
+            r#"SELECT id as "buffer_id: BufferId", locked_by as "locked_by!: UserId",
+                      locked_at as "locked_at!", lock_intent as intent
+               FROM buffers
+               WHERE locked_by IS NOT NULL
+               ORDER BY locked_at ASC, id ASC"#
+        )
+        .fetch(&self.pool)
+        .map_err(Error::from)
+    }
+
+    /// Returns the lock that has been held the longest, if any
+    pub async fn oldest_lock(&self) -> Result<Option<LockRecord>> {
+        Ok(self.longest_held_locks(1).await?.into_iter().next())
+    }
+
+    /// Returns up to `n` locks, longest held first
+    pub async fn longest_held_locks(&self, n: i64) -> Result<Vec<LockRecord>> {
+        let pool = self.read_pool().await;
+        let locks = sqlx::query_as!(
+            LockRecord,
+            r#"SELECT id as "buffer_id: BufferId", locked_by as "locked_by!: UserId",
+                      locked_at as "locked_at!", lock_intent as intent
+               FROM buffers
+               WHERE locked_by IS NOT NULL
+               ORDER BY locked_at ASC, id ASC
+               LIMIT $1"#,
+            n
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        Ok(locks)
+    }
+
+    /// Returns the number of buffers currently held by any user
+    pub async fn locked_buffer_count(&self) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM buffers WHERE locked_by IS NOT NULL"#
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Returns how many distinct users currently hold at least one lock
+    pub async fn active_editor_count(&self) -> Result<i64> {
+        let pool = self.read_pool().await;
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(DISTINCT locked_by) as "count!" FROM buffers WHERE locked_by IS NOT NULL"#
+        )
+        .fetch_one(&pool)
+        .await?;
+
+        Ok(count)
+    }
 }
@@ -0,0 +1,39 @@
+use serde::Serialize;
+
+use super::{Database, LockRecord};
+use crate::Result;
+
+/// Connection pool usage at a point in time
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct PoolStats {
+    /// Open connections, idle or in use
+    pub size: u32,
+    pub idle: usize,
+}
+
+/// Snapshot of pool and lock state, meant to be attached to support requests
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostics {
+    pub pool: PoolStats,
+    pub locked_buffers: i64,
+    pub oldest_lock: Option<LockRecord>,
+    pub contention_ratio: f64,
+}
+
+impl Database {
+    pub fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+        }
+    }
+
+    pub async fn diagnostics(&self) -> Result<Diagnostics> {
+        Ok(Diagnostics {
+            pool: self.pool_stats(),
+            locked_buffers: self.buffers.locked_buffer_count().await?,
+            oldest_lock: self.buffers.oldest_lock().await?,
+            contention_ratio: self.buffers.metrics().contention_ratio(),
+        })
+    }
+}
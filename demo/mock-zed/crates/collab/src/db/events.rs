@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::audit::AuditContext;
+use super::{AuditAction, BufferManager};
+use crate::{BufferId, UserId};
+
+/// A change in a buffer's lock state, published to every registered `EventSink`
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LockEvent {
+    Acquired {
+        buffer_id: BufferId,
+        user_id: UserId,
+        at: DateTime<Utc>,
+    },
+    Released {
+        buffer_id: BufferId,
+        user_id: UserId,
+        at: DateTime<Utc>,
+    },
+    /// Sent once by `Database::shutdown`; no events follow it
+    Closed { at: DateTime<Utc> },
+}
+
+/// Destination for lock events
+///
+/// `send` is called inline on the lock path, so implementations must hand
+/// slow work off to a task rather than block.
+pub trait EventSink: Send + Sync {
+    fn send(&self, event: &LockEvent);
+}
+
+impl BufferManager {
+    pub fn add_event_sink(&self, sink: Arc<dyn EventSink>) {
+        self.event_sinks.write().unwrap().push(sink);
+    }
+
+    /// Sends `LockEvent::Closed` to every sink and unregisters them all
+    pub(super) fn close_event_sinks(&self) {
+        let sinks = std::mem::take(&mut *self.event_sinks.write().unwrap());
+        let event = LockEvent::Closed { at: Utc::now() };
+        for sink in &sinks {
+            sink.send(&event);
+        }
+    }
+
+    /// Audits a lock state change and publishes it to the event sinks
+    ///
+    /// The change has already been committed by the time this runs, so an
+    /// audit failure is logged rather than returned: the caller still holds
+    /// (or no longer holds) the lock either way.
+    pub(super) async fn record_lock_change(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        action: AuditAction,
+    ) {
+        self.record_lock_change_with(buffer_id, user_id, action, AuditContext::default())
+            .await
+    }
+
+    /// Like `record_lock_change`, storing extra request details with the audit entry
+    pub(super) async fn record_lock_change_with(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        action: AuditAction,
+        context: AuditContext<'_>,
+    ) {
+        if action == AuditAction::Acquire {
+            self.metrics.record_acquisition();
+        }
+        if self.config.enable_audit {
+            if let Err(error) = self.record_audit(buffer_id, user_id, action, context).await {
+                tracing::error!(
+                    %error,
+                    buffer_id = buffer_id.0,
+                    user_id = user_id.0,
+                    ?action,
+                    "failed to audit committed lock change"
+                );
+            }
+        }
+        if !self.config.enable_events {
+            return;
+        }
+
+        let at = Utc::now();
+        let event = match action {
+            AuditAction::Acquire => LockEvent::Acquired { buffer_id, user_id, at },
+            AuditAction::Release => LockEvent::Released { buffer_id, user_id, at },
+        };
+        for sink in self.event_sinks.read().unwrap().iter() {
+            sink.send(&event);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::{BufferId, UserId, Error, Result};
+
+/// In-process lock table used while the database is unreachable
+///
+/// Locks taken here only exclude other requests handled by this process, so
+/// callers should check `BufferManager::is_degraded` before relying on them.
+/// Like the database, a holder re-acquiring nests the lock, and it is only
+/// released once every acquisition has been released.
+#[derive(Default)]
+pub struct MemoryFallback {
+    locks: Mutex<HashMap<BufferId, (UserId, u32)>>,
+}
+
+impl MemoryFallback {
+    pub fn acquire(&self, buffer_id: BufferId, user_id: UserId) -> Result<bool> {
+        let mut locks = self.locks.lock().unwrap();
+        match locks.get_mut(&buffer_id) {
+            Some((holder, count)) if *holder == user_id => *count += 1,
+            Some((holder, _)) => {
+                return Err(Error::BufferLocked {
+                    buffer_id,
+                    held_by: Some(*holder),
+                });
+            }
+            None => {
+                locks.insert(buffer_id, (user_id, 1));
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Releases one level of the lock if `user_id` holds it, returning whether it did
+    pub fn release(&self, buffer_id: BufferId, user_id: UserId) -> bool {
+        let mut locks = self.locks.lock().unwrap();
+        let Some((holder, count)) = locks.get_mut(&buffer_id) else {
+            return false;
+        };
+        if *holder != user_id {
+            return false;
+        }
+
+        *count -= 1;
+        if *count == 0 {
+            locks.remove(&buffer_id);
+        }
+        true
+    }
+
+    /// Returns the locks currently held in memory with how deeply each is nested
+    pub fn locks(&self) -> Vec<(BufferId, UserId, u32)> {
+        self.locks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(buffer_id, (user_id, count))| (*buffer_id, *user_id, *count))
+            .collect()
+    }
+
+    pub(super) fn forget(&self, buffer_id: BufferId) {
+        self.locks.lock().unwrap().remove(&buffer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUFFER: BufferId = BufferId(1);
+    const ALICE: UserId = UserId(1);
+    const BOB: UserId = UserId(2);
+
+    #[test]
+    fn holder_nests_and_unwinds_one_level_per_release() {
+        let fallback = MemoryFallback::default();
+        assert!(fallback.acquire(BUFFER, ALICE).unwrap());
+        assert!(fallback.acquire(BUFFER, ALICE).unwrap());
+        assert_eq!(fallback.locks(), vec![(BUFFER, ALICE, 2)]);
+
+        assert!(fallback.release(BUFFER, ALICE));
+        assert_eq!(fallback.locks(), vec![(BUFFER, ALICE, 1)]);
+        assert!(fallback.release(BUFFER, ALICE));
+        assert!(fallback.locks().is_empty());
+        assert!(!fallback.release(BUFFER, ALICE));
+    }
+
+    #[test]
+    fn other_users_are_locked_out_until_release() {
+        let fallback = MemoryFallback::default();
+        fallback.acquire(BUFFER, ALICE).unwrap();
+
+        assert!(matches!(
+            fallback.acquire(BUFFER, BOB),
+            Err(Error::BufferLocked { buffer_id: BUFFER, held_by: Some(ALICE) })
+        ));
+        assert!(!fallback.release(BUFFER, BOB));
+
+        fallback.release(BUFFER, ALICE);
+        assert!(fallback.acquire(BUFFER, BOB).unwrap());
+    }
+
+    #[test]
+    fn forget_drops_the_lock_however_deeply_nested() {
+        let fallback = MemoryFallback::default();
+        fallback.acquire(BUFFER, ALICE).unwrap();
+        fallback.acquire(BUFFER, ALICE).unwrap();
+
+        fallback.forget(BUFFER);
+        assert!(fallback.locks().is_empty());
+    }
+}
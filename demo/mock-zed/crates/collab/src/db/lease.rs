@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use super::{BufferManager, Database, LeaseToken};
+use crate::{BufferId, UserId, Error, Result};
+
+/// A buffer lock kept alive by a background renewal task
+///
+/// Stopping the handle, or dropping it, ends renewal and releases the lock.
+pub struct LeaseHandle {
+    buffer_id: BufferId,
+    token: LeaseToken,
+    lost: watch::Receiver<bool>,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl LeaseHandle {
+    pub fn buffer_id(&self) -> BufferId {
+        self.buffer_id
+    }
+
+    pub fn token(&self) -> LeaseToken {
+        self.token
+    }
+
+    /// Whether a renewal found the lock released or taken over
+    pub fn is_lost(&self) -> bool {
+        *self.lost.borrow()
+    }
+
+    /// Waits until a renewal finds the lock released or taken over
+    pub async fn lost(&mut self) {
+        while !*self.lost.borrow_and_update() {
+            if self.lost.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Stops renewing and releases the lock
+    ///
+    /// If the lease was already lost, returns the error the failed renewal got
+    /// instead, and there is nothing left to release. A renewal task cancelled
+    /// by its runtime shutting down counts as stopped; the lock it held is left
+    /// to expire. A panic in the task is resumed here.
+    pub async fn stop(self) -> Result<()> {
+        let _ = self.stop.send(());
+        match self.task.await {
+            Ok(result) => result,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl Database {
+    /// Acquires a lock and renews it every `renew_interval` until the handle is stopped
+    ///
+    /// `renew_interval` should sit well inside `Config::lock_expiry`; one that
+    /// is zero or not shorter than it fails with `Error::InvalidInterval`
+    /// before the lock is taken. A renewal that fails on a database error is
+    /// retried on the next tick; one that finds the lock gone marks the lease
+    /// lost and ends renewal.
+    pub async fn lease_buffer(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        renew_interval: Duration,
+    ) -> Result<LeaseHandle> {
+        if renew_interval.is_zero() || renew_interval >= self.buffers.config.lock_expiry {
+            return Err(Error::InvalidInterval(renew_interval));
+        }
+
+        let token = self.buffers.acquire_lease(buffer_id, user_id).await?;
+
+        let (lost_tx, lost) = watch::channel(false);
+        let (stop, stopped) = oneshot::channel();
+        let lease = Lease {
+            buffers: self.buffers.clone(),
+            buffer_id,
+            user_id,
+            token,
+        };
+        let task = tokio::spawn(lease.run(renew_interval, stopped, lost_tx));
+
+        Ok(LeaseHandle {
+            buffer_id,
+            token,
+            lost,
+            stop,
+            task,
+        })
+    }
+}
+
+struct Lease {
+    buffers: Arc<BufferManager>,
+    buffer_id: BufferId,
+    user_id: UserId,
+    token: LeaseToken,
+}
+
+impl Lease {
+    async fn run(
+        self,
+        renew_interval: Duration,
+        mut stopped: oneshot::Receiver<()>,
+        lost: watch::Sender<bool>,
+    ) -> Result<()> {
+        let mut ticker = tokio::time::interval(renew_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately, and the lock was only just taken
+        ticker.tick().await;
+
+        loop {
+            tokio::select! {
+                // Also taken when the handle is dropped
+                _ = &mut stopped => {
+                    return self
+                        .buffers
+                        .release_lease(self.buffer_id, self.user_id, self.token)
+                        .await;
+                }
+                _ = ticker.tick() => match self.renew().await {
+                    Ok(()) => {}
+                    Err(error @ (Error::StaleLease | Error::NotLockHolder | Error::NotFound)) => {
+                        tracing::warn!(buffer_id = self.buffer_id.0, %error, "buffer lease lost");
+                        let _ = lost.send(true);
+                        return Err(error);
+                    }
+                    Err(error) => tracing::error!(
+                        buffer_id = self.buffer_id.0,
+                        %error,
+                        "failed to renew buffer lease"
+                    ),
+                },
+            }
+        }
+    }
+
+    async fn renew(&self) -> Result<()> {
+        self.buffers
+            .renew_lease(self.buffer_id, self.user_id, self.token)
+            .await
+    }
+}
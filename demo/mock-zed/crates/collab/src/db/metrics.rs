@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{BufferId, Config};
+
+/// Number of one-second buckets in the acquisition rate window
+const RATE_WINDOW_SECS: usize = 60;
+
+/// In-process counters describing buffer lock behavior
+///
+/// When metrics are disabled in `Config`, recording is a no-op and every
+/// reading stays at zero.
+pub struct Metrics {
+    enabled: bool,
+    hold_durations: Histogram,
+    contention: Mutex<HashMap<BufferId, u64>>,
+    buffer_attempts: Mutex<HashMap<BufferId, u64>>,
+    wait_times: Mutex<WaitTimeStats>,
+    attempts: AtomicU64,
+    contended: AtomicU64,
+    acquisitions: Mutex<RateWindow>,
+}
+
+impl Metrics {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            enabled: config.enable_metrics,
+            hold_durations: Histogram::new(config.hold_duration_buckets.clone()),
+            contention: Mutex::default(),
+            buffer_attempts: Mutex::default(),
+            wait_times: Mutex::default(),
+            attempts: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            acquisitions: Mutex::new(RateWindow::new()),
+        }
+    }
+
+    /// How long locks were held between acquisition and release
+    pub fn hold_duration_histogram(&self) -> &Histogram {
+        &self.hold_durations
+    }
+
+    pub(super) fn record_hold_duration(&self, held_for: Duration) {
+        if !self.enabled {
+            return;
+        }
+        self.hold_durations.record(held_for);
+    }
+
+    /// Average successful acquisitions per second over the last minute
+    pub fn acquisitions_per_second(&self) -> f64 {
+        self.acquisitions.lock().unwrap().rate(Instant::now())
+    }
+
+    pub(super) fn record_acquisition(&self) {
+        if !self.enabled {
+            return;
+        }
+        self.acquisitions.lock().unwrap().record(Instant::now());
+    }
+
+    /// Fraction of acquisition attempts rejected because the buffer was held
+    pub fn contention_ratio(&self) -> f64 {
+        let attempts = self.attempts.load(Ordering::Relaxed);
+        if attempts == 0 {
+            return 0.0;
+        }
+
+        self.contended.load(Ordering::Relaxed) as f64 / attempts as f64
+    }
+
+    /// Fraction of attempts on `buffer_id` rejected because it was held
+    pub fn buffer_contention_ratio(&self, buffer_id: BufferId) -> f64 {
+        let attempts = self
+            .buffer_attempts
+            .lock()
+            .unwrap()
+            .get(&buffer_id)
+            .copied()
+            .unwrap_or_default();
+        if attempts == 0 {
+            return 0.0;
+        }
+
+        self.contention_count(buffer_id) as f64 / attempts as f64
+    }
+
+    pub(super) fn record_attempt(&self, buffer_id: BufferId) {
+        if !self.enabled {
+            return;
+        }
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        *self.buffer_attempts.lock().unwrap().entry(buffer_id).or_default() += 1;
+    }
+
+    /// Number of acquisitions of `buffer_id` rejected because it was held
+    pub fn contention_count(&self, buffer_id: BufferId) -> u64 {
+        self.contention
+            .lock()
+            .unwrap()
+            .get(&buffer_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the `n` buffers with the most rejected acquisitions, most contended first
+    pub fn top_contended(&self, n: usize) -> Vec<(BufferId, u64)> {
+        let mut counts: Vec<_> = self
+            .contention
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(buffer_id, count)| (*buffer_id, *count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0 .0.cmp(&b.0 .0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// How long queued acquisitions waited before being granted the lock
+    pub fn wait_times(&self) -> WaitTimeStats {
+        *self.wait_times.lock().unwrap()
+    }
+
+    pub(super) fn record_wait_time(&self, waited: Duration) {
+        if !self.enabled {
+            return;
+        }
+        let mut stats = self.wait_times.lock().unwrap();
+        stats.count += 1;
+        stats.total += waited;
+        stats.max = stats.max.max(waited);
+    }
+
+    pub(super) fn record_contention(&self, buffer_id: BufferId) {
+        if !self.enabled {
+            return;
+        }
+        self.contended.fetch_add(1, Ordering::Relaxed);
+        *self.contention.lock().unwrap().entry(buffer_id).or_default() += 1;
+    }
+}
+
+/// Event counts over a sliding one-minute window, in one-second buckets
+///
+/// Each bucket remembers which second it was last used for, so stale buckets
+/// are recognized and reset lazily instead of by a timer.
+struct RateWindow {
+    started_at: Instant,
+    buckets: [(u64, u64); RATE_WINDOW_SECS],
+}
+
+impl RateWindow {
+    fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            buckets: [(0, 0); RATE_WINDOW_SECS],
+        }
+    }
+
+    fn record(&mut self, now: Instant) {
+        let second = now.duration_since(self.started_at).as_secs();
+        let bucket = &mut self.buckets[second as usize % RATE_WINDOW_SECS];
+        if bucket.0 != second {
+            *bucket = (second, 0);
+        }
+        bucket.1 += 1;
+    }
+
+    fn rate(&self, now: Instant) -> f64 {
+        let second = now.duration_since(self.started_at).as_secs();
+        let total: u64 = self
+            .buckets
+            .iter()
+            .filter(|(bucket_second, _)| {
+                second.saturating_sub(*bucket_second) < RATE_WINDOW_SECS as u64
+            })
+            .map(|(_, count)| count)
+            .sum();
+
+        total as f64 / RATE_WINDOW_SECS as f64
+    }
+}
+
+/// Aggregate wait times of granted queued acquisitions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WaitTimeStats {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl WaitTimeStats {
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.total.div_f64(self.count as f64))
+    }
+}
+
+/// Counts of observed durations grouped into fixed buckets
+///
+/// Each bucket counts values up to and including its upper bound; values
+/// above every bound land in a final overflow bucket.
+pub struct Histogram {
+    bounds: Vec<Duration>,
+    counts: Vec<AtomicU64>,
+}
+
+impl Histogram {
+    pub fn new(mut bounds: Vec<Duration>) -> Self {
+        bounds.sort();
+        bounds.dedup();
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+
+        Self { bounds, counts }
+    }
+
+    pub fn record(&self, value: Duration) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns each bucket's upper bound with its count, the overflow bucket last with `None`
+    pub fn bucket_counts(&self) -> Vec<(Option<Duration>, u64)> {
+        self.bounds
+            .iter()
+            .copied()
+            .map(Some)
+            .chain([None])
+            .zip(&self.counts)
+            .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn histogram_counts_values_up_to_and_including_each_bound() {
+        let histogram = Histogram::new(vec![ms(10), ms(100)]);
+        for value in [ms(0), ms(10), ms(11), ms(100), ms(101), ms(5_000)] {
+            histogram.record(value);
+        }
+
+        assert_eq!(
+            histogram.bucket_counts(),
+            vec![(Some(ms(10)), 2), (Some(ms(100)), 2), (None, 2)]
+        );
+    }
+
+    #[test]
+    fn histogram_sorts_and_dedups_bounds() {
+        let histogram = Histogram::new(vec![ms(100), ms(10), ms(100)]);
+        histogram.record(ms(50));
+
+        assert_eq!(
+            histogram.bucket_counts(),
+            vec![(Some(ms(10)), 0), (Some(ms(100)), 1), (None, 0)]
+        );
+    }
+
+    #[test]
+    fn histogram_without_bounds_has_only_the_overflow_bucket() {
+        let histogram = Histogram::new(Vec::new());
+        histogram.record(ms(1));
+
+        assert_eq!(histogram.bucket_counts(), vec![(None, 1)]);
+    }
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn rate_window_averages_events_over_the_window() {
+        let mut window = RateWindow::new();
+        let start = window.started_at;
+        for _ in 0..30 {
+            window.record(start);
+        }
+        for _ in 0..30 {
+            window.record(start + secs(59));
+        }
+
+        assert_eq!(window.rate(start + secs(59)), 1.0);
+    }
+
+    #[test]
+    fn rate_window_drops_events_older_than_the_window() {
+        let mut window = RateWindow::new();
+        let start = window.started_at;
+        window.record(start);
+        window.record(start + secs(30));
+
+        assert_eq!(window.rate(start + secs(60)), 1.0 / 60.0);
+        assert_eq!(window.rate(start + secs(90)), 0.0);
+    }
+
+    #[test]
+    fn rate_window_resets_a_reused_bucket() {
+        let mut window = RateWindow::new();
+        let start = window.started_at;
+        for _ in 0..5 {
+            window.record(start);
+        }
+        // Second 60 maps onto the same bucket as second 0
+        window.record(start + secs(60));
+
+        assert_eq!(window.rate(start + secs(60)), 1.0 / 60.0);
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use super::{BufferManager, Database};
+use crate::{Error, Result};
+
+/// Background maintenance task, either the lock reaper or the audit pruner
+///
+/// Dropping the handle also stops the task, without waiting for an
+/// in-progress sweep; call `stop` to wait for it.
+#[must_use = "dropping the handle stops the task"]
+pub struct ReaperHandle {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl ReaperHandle {
+    /// Stops the task and waits for any in-progress sweep to finish
+    pub async fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.task.await;
+    }
+}
+
+impl Database {
+    /// Periodically releases locks older than `Config::lock_expiry`
+    ///
+    /// Fails with `Error::InvalidInterval` for a zero `interval`.
+    pub fn start_reaper(&self, interval: Duration) -> Result<ReaperHandle> {
+        if interval.is_zero() {
+            return Err(Error::InvalidInterval(interval));
+        }
+
+        let buffers = self.buffers.clone();
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(run_reaper(buffers, interval, stopped));
+
+        Ok(ReaperHandle { stop, task })
+    }
+
+    /// Periodically deletes audit entries older than `older_than`
+    ///
+    /// Fails with `Error::InvalidInterval` for a zero `interval`.
+    pub fn start_audit_pruner(
+        &self,
+        interval: Duration,
+        older_than: Duration,
+    ) -> Result<ReaperHandle> {
+        if interval.is_zero() {
+            return Err(Error::InvalidInterval(interval));
+        }
+
+        let buffers = self.buffers.clone();
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(run_audit_pruner(buffers, interval, older_than, stopped));
+
+        Ok(ReaperHandle { stop, task })
+    }
+}
+
+async fn run_reaper(
+    buffers: Arc<BufferManager>,
+    interval: Duration,
+    mut stopped: oneshot::Receiver<()>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = &mut stopped => break,
+            _ = ticker.tick() => match buffers.reap_expired_locks().await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "reaped expired buffer locks"),
+                Err(error) => tracing::error!(%error, "failed to reap expired buffer locks"),
+            },
+        }
+    }
+}
+
+async fn run_audit_pruner(
+    buffers: Arc<BufferManager>,
+    interval: Duration,
+    older_than: Duration,
+    mut stopped: oneshot::Receiver<()>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = &mut stopped => break,
+            _ = ticker.tick() => match buffers.prune_audit(older_than).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "pruned old buffer lock audit entries"),
+                Err(error) => tracing::error!(%error, "failed to prune buffer lock audit entries"),
+            },
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio::time::Instant;
+
+use super::{BufferManager, Database};
+use crate::Result;
+
+/// How long one replica lag measurement decides where reads go
+const REPLICA_LAG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Database {
+    /// Sends `read_pool` traffic to `replica` while it keeps up with the primary
+    pub fn with_replica(self, replica: PgPool) -> Self {
+        self.buffers.set_replica(replica);
+        self
+    }
+
+    /// How far the replica's replayed WAL trails the primary
+    ///
+    /// See `BufferManager::replica_lag`.
+    pub async fn replica_lag(&self) -> Result<Duration> {
+        self.buffers.replica_lag().await
+    }
+
+    /// Returns the pool reads should use; see `BufferManager::read_pool`
+    pub async fn read_pool(&self) -> PgPool {
+        self.buffers.read_pool().await
+    }
+}
+
+impl BufferManager {
+    pub(super) fn set_replica(&self, replica: PgPool) {
+        *self.replica.write().unwrap() = Some(replica);
+        *self.replica_check.lock().unwrap() = None;
+    }
+
+    pub(super) fn replica(&self) -> Option<PgPool> {
+        self.replica.read().unwrap().clone()
+    }
+
+    /// How far the replica's replayed WAL trails the primary
+    ///
+    /// Zero when the replica has replayed everything the primary has written,
+    /// or when no replica is configured and reads are served by the primary.
+    /// A replica that has never replayed a transaction reports `Duration::MAX`.
+    pub async fn replica_lag(&self) -> Result<Duration> {
+        let Some(replica) = self.replica() else {
+            return Ok(Duration::ZERO);
+        };
+
+        let primary_lsn = sqlx::query_scalar!(r#"SELECT pg_current_wal_lsn()::text as "lsn!""#)
+            .fetch_one(&self.pool)
+            .await?;
+        let replay = sqlx::query!(
+            r#"SELECT COALESCE(pg_last_wal_replay_lsn() >= $1::text::pg_lsn, false) as "caught_up!",
+                      (EXTRACT(EPOCH FROM NOW() - pg_last_xact_replay_timestamp()) * 1000)::bigint
+                          as lag_ms"#,
+            primary_lsn
+        )
+        .fetch_one(&replica)
+        .await?;
+
+        if replay.caught_up {
+            return Ok(Duration::ZERO);
+        }
+
+        Ok(replay
+            .lag_ms
+            .map_or(Duration::MAX, |lag_ms| Duration::from_millis(lag_ms.max(0) as u64)))
+    }
+
+    /// Returns the pool reads should use: the replica while its lag is within
+    /// `Config::max_replica_lag`, and the primary otherwise
+    ///
+    /// Serves the read-only reporting queries, such as `buffer_info`,
+    /// `active_locks` and the audit log; lock decisions always read the
+    /// primary. The lag is measured at most once per second. With
+    /// `Config::table_prefix` set the primary is always used, since a standby
+    /// can't create the views that map the prefixed tables.
+    pub async fn read_pool(&self) -> PgPool {
+        let Some(replica) = self.replica() else {
+            return self.pool.clone();
+        };
+        if self.config.table_prefix.is_some() {
+            return self.pool.clone();
+        }
+
+        let cached = *self.replica_check.lock().unwrap();
+        let use_replica = match cached {
+            Some((checked_at, within_lag)) if checked_at.elapsed() < REPLICA_LAG_CHECK_INTERVAL => {
+                within_lag
+            }
+            _ => {
+                let within_lag = self.replica_within_lag().await;
+                *self.replica_check.lock().unwrap() = Some((Instant::now(), within_lag));
+                within_lag
+            }
+        };
+
+        if use_replica {
+            replica
+        } else {
+            self.pool.clone()
+        }
+    }
+
+    async fn replica_within_lag(&self) -> bool {
+        match self.replica_lag().await {
+            Ok(lag) if lag <= self.config.max_replica_lag => true,
+            Ok(lag) => {
+                tracing::warn!(
+                    lag_ms = lag.as_millis() as u64,
+                    "replica lagging, reading from primary"
+                );
+                false
+            }
+            Err(error) => {
+                tracing::warn!(%error, "failed to measure replica lag, reading from primary");
+                false
+            }
+        }
+    }
+}
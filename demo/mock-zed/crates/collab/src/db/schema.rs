@@ -0,0 +1,189 @@
+use sqlx::{Connection, Executor, PgConnection};
+
+use super::{quote_identifier, Database};
+use crate::{Error, Result};
+
+/// Tables renamed by `Config::table_prefix`, along with their indexes
+const PREFIXED_TABLES: &[&str] = &["buffers", "buffer_lock_waiters", "buffer_lock_audit"];
+
+/// Columns of `buffers` that queries in this crate depend on
+const REQUIRED_BUFFER_COLUMNS: &[&str] = &[
+    "id",
+    "locked_by",
+    "locked_at",
+    "lock_count",
+    "version",
+    "workspace_id",
+    "path",
+    "last_activity_at",
+    "last_activity_by",
+    "lock_intent",
+    "content",
+    "content_hash",
+    "deleted_at",
+    "last_holder",
+    "lease_token",
+];
+
+impl Database {
+    /// Applies any of the crate's migrations that haven't run against this database yet
+    ///
+    /// With `Config::table_prefix` set, they are applied to the prefixed tables
+    /// and tracked separately for each prefix. Columns they add only show up
+    /// on connections opened afterwards, so prefer `Database::bootstrap`.
+    pub async fn migrate(&self) -> Result<()> {
+        if let Some(prefix) = &self.buffers.config.table_prefix {
+            let mut conn = self.pool.acquire().await?;
+            return migrate_prefixed(&mut conn, prefix).await;
+        }
+
+        sqlx::migrate!("./migrations").run(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Checks that the `buffers` table has every column this crate queries
+    pub async fn verify_schema(&self) -> Result<()> {
+        let table = match &self.buffers.config.table_prefix {
+            Some(prefix) => format!("{prefix}buffers"),
+            None => "buffers".to_string(),
+        };
+        let columns = sqlx::query_scalar!(
+            r#"SELECT column_name as "column_name!"
+               FROM information_schema.columns
+               WHERE table_schema = current_schema() AND table_name = $1"#,
+            table
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let missing: Vec<String> = REQUIRED_BUFFER_COLUMNS
+            .iter()
+            .filter(|required| !columns.iter().any(|column| column == *required))
+            .map(|required| required.to_string())
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::SchemaMismatch { missing })
+        }
+    }
+}
+
+/// Runs the crate's migrations against the tables named with `prefix`
+///
+/// sqlx's migrator tracks a single set of tables per database, so applied
+/// versions are recorded in a `{prefix}buffer_migrations` table instead.
+pub(super) async fn migrate_prefixed(conn: &mut PgConnection, prefix: &str) -> Result<()> {
+    let migrations_table = quote_identifier(&format!("{prefix}buffer_migrations"));
+    let mut tx = conn.begin().await?;
+    tx.execute(
+        format!(
+            "CREATE TABLE IF NOT EXISTS {migrations_table} (
+                 version BIGINT PRIMARY KEY,
+                 applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+             )"
+        )
+        .as_str(),
+    )
+    .await?;
+    // Concurrent bootstraps for the same prefix apply each migration once
+    tx.execute(format!("LOCK TABLE {migrations_table} IN EXCLUSIVE MODE").as_str()).await?;
+
+    let applied: Vec<i64> = sqlx::query_scalar(&format!("SELECT version FROM {migrations_table}"))
+        .fetch_all(&mut *tx)
+        .await?;
+    let migrator = sqlx::migrate!("./migrations");
+    for migration in migrator.iter() {
+        if migration.migration_type.is_down_migration() || applied.contains(&migration.version) {
+            continue;
+        }
+        tx.execute(prefix_table_names(&migration.sql, prefix).as_str()).await?;
+        sqlx::query(&format!("INSERT INTO {migrations_table} (version) VALUES ($1)"))
+            .bind(migration.version)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Statements shadowing each collab table, for the current connection only,
+/// with a view of its prefixed counterpart
+///
+/// Temporary relations are looked up before the `search_path`, and a view
+/// over a single table is updatable, so unprefixed queries read and write
+/// the prefixed tables unchanged.
+pub(super) fn prefix_views(prefix: &str) -> String {
+    PREFIXED_TABLES
+        .iter()
+        .map(|table| {
+            format!(
+                "CREATE TEMP VIEW {} AS SELECT * FROM {};",
+                quote_identifier(table),
+                quote_identifier(&format!("{prefix}{table}"))
+            )
+        })
+        .collect()
+}
+
+/// Prefixes every reference to a collab table or one of its indexes in `sql`
+fn prefix_table_names(sql: &str, prefix: &str) -> String {
+    let mut prefixed = String::with_capacity(sql.len());
+    let mut rest = sql;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        prefixed.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        let is_table_name = PREFIXED_TABLES.iter().any(|table| {
+            word.strip_prefix(table)
+                .is_some_and(|suffix| suffix.is_empty() || suffix.starts_with('_'))
+        });
+        if is_table_name {
+            prefixed.push_str(&quote_identifier(&format!("{prefix}{word}")));
+        } else {
+            prefixed.push_str(word);
+        }
+        rest = &rest[end..];
+    }
+    prefixed.push_str(rest);
+
+    prefixed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_tables_and_their_indexes() {
+        let sql = "CREATE INDEX buffers_locked_by_idx ON buffers (locked_by);\n\
+                   ALTER TABLE buffer_lock_waiters ADD COLUMN expires_at TIMESTAMPTZ;";
+
+        assert_eq!(
+            prefix_table_names(sql, "acme_"),
+            "CREATE INDEX \"acme_buffers_locked_by_idx\" ON \"acme_buffers\" (locked_by);\n\
+             ALTER TABLE \"acme_buffer_lock_waiters\" ADD COLUMN expires_at TIMESTAMPTZ;"
+        );
+    }
+
+    #[test]
+    fn leaves_other_identifiers_alone() {
+        let sql = "SELECT buffersize, lock_count, buffer_lock_expired(locked_at, NULL, 1) FROM other";
+
+        assert_eq!(prefix_table_names(sql, "acme_"), sql);
+    }
+
+    #[test]
+    fn quotes_prefixes_that_need_it() {
+        assert_eq!(
+            prefix_table_names("DROP TABLE buffer_lock_audit", "Tenant \"A\" "),
+            "DROP TABLE \"Tenant \"\"A\"\" buffer_lock_audit\""
+        );
+    }
+}
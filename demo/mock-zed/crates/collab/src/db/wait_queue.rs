@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use super::BufferManager;
+use crate::{BufferId, UserId, Result};
+
+/// Position of a caller in a buffer's `buffer_lock_waiters` queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct WaiterId(i64);
+
+/// A caller's wait queue entry, removed when the guard is dropped
+///
+/// Dropping covers waits abandoned midway, such as a timed out or cancelled
+/// future. An entry left behind by a crash is skipped and purged once its
+/// `expires_at` passes.
+pub(super) struct WaiterGuard {
+    pool: PgPool,
+    id: WaiterId,
+    dequeued: bool,
+}
+
+impl WaiterGuard {
+    pub(super) fn id(&self) -> WaiterId {
+        self.id
+    }
+
+    /// Leaves the queue, waiting for the entry to be deleted
+    ///
+    /// On failure the guard's drop tries the delete once more.
+    pub(super) async fn dequeue(mut self) -> Result<()> {
+        delete_waiter(&self.pool, self.id).await?;
+        self.dequeued = true;
+        Ok(())
+    }
+}
+
+impl Drop for WaiterGuard {
+    fn drop(&mut self) {
+        if self.dequeued {
+            return;
+        }
+
+        // Outside a runtime the entry is left to expire instead
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let pool = self.pool.clone();
+        let id = self.id;
+        runtime.spawn(async move {
+            if let Err(error) = delete_waiter(&pool, id).await {
+                tracing::warn!(%error, "failed to remove abandoned wait queue entry");
+            }
+        });
+    }
+}
+
+async fn delete_waiter(pool: &PgPool, waiter: WaiterId) -> Result<()> {
+    sqlx::query!("DELETE FROM buffer_lock_waiters WHERE id = $1", waiter.0)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+impl BufferManager {
+    /// Joins the wait queue for `buffer_id` until `expires_in` from now, or
+    /// returns `None` if it already holds `max_waiters` live entries
+    ///
+    /// Entries past their expiry are purged first and never count.
+    pub(super) async fn enqueue_waiter(
+        &self,
+        buffer_id: BufferId,
+        user_id: UserId,
+        max_waiters: Option<i64>,
+        expires_in: Duration,
+    ) -> Result<Option<WaiterGuard>> {
+        let mut tx = self.pool.begin().await?;
+
+        // Without this, callers joining at once could each count the others'
+        // entries as missing and overshoot the cap together. The key is hashed
+        // from the buffer so it doesn't share the user ids the quota locks on.
+        if max_waiters.is_some() {
+            sqlx::query!(
+                "SELECT 1 as locked
+                 FROM pg_advisory_xact_lock(hashtextextended('buffer_lock_waiters:' || $1::bigint, 0))",
+                buffer_id.0
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let id = sqlx::query_scalar!(
+            "WITH purged AS (
+                 DELETE FROM buffer_lock_waiters WHERE buffer_id = $1 AND expires_at < NOW()
+             )
+             INSERT INTO buffer_lock_waiters (buffer_id, user_id, expires_at)
+             SELECT $1, $2, NOW() + $4::bigint * INTERVAL '1 millisecond'
+             WHERE $3::bigint IS NULL
+                OR (SELECT COUNT(*) FROM buffer_lock_waiters
+                    WHERE buffer_id = $1 AND expires_at >= NOW()) < $3
+             RETURNING id",
+            buffer_id.0,
+            user_id.0,
+            max_waiters,
+            expires_in.as_millis() as i64
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(id.map(|id| WaiterGuard {
+            pool: self.pool.clone(),
+            id: WaiterId(id),
+            dequeued: false,
+        }))
+    }
+
+    /// Whether no live entry queued for `buffer_id` ahead of `waiter`
+    pub(super) async fn is_next_waiter(
+        &self,
+        buffer_id: BufferId,
+        waiter: WaiterId,
+    ) -> Result<bool> {
+        let is_next = sqlx::query_scalar!(
+            r#"WITH purged AS (
+                   DELETE FROM buffer_lock_waiters WHERE buffer_id = $1 AND expires_at < NOW()
+               )
+               SELECT NOT EXISTS(
+                   SELECT 1 FROM buffer_lock_waiters
+                   WHERE buffer_id = $1 AND id < $2 AND expires_at >= NOW()
+               ) as "is_next!""#,
+            buffer_id.0,
+            waiter.0
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(is_next)
+    }
+
+    /// Returns every queued caller that has been waiting longer than `threshold`, longest first
+    pub async fn starved_waiters(
+        &self,
+        threshold: Duration,
+    ) -> Result<Vec<(BufferId, UserId, Duration)>> {
+        let rows = sqlx::query!(
+            r#"SELECT buffer_id as "buffer_id: BufferId", user_id as "user_id: UserId",
+                      (EXTRACT(EPOCH FROM NOW() - created_at) * 1000)::bigint as "waited_ms!"
+               FROM buffer_lock_waiters
+               WHERE created_at < NOW() - $1::bigint * INTERVAL '1 millisecond'
+                 AND expires_at >= NOW()
+               ORDER BY created_at ASC, id ASC"#,
+            threshold.as_millis() as i64
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let waited = Duration::from_millis(row.waited_ms.max(0) as u64);
+                (row.buffer_id, row.user_id, waited)
+            })
+            .collect())
+    }
+
+    /// Number of callers currently queued for `buffer_id`, purging expired entries
+    pub async fn waiter_count(&self, buffer_id: BufferId) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"WITH purged AS (
+                   DELETE FROM buffer_lock_waiters WHERE buffer_id = $1 AND expires_at < NOW()
+               )
+               SELECT COUNT(*) as "count!"
+               FROM buffer_lock_waiters
+               WHERE buffer_id = $1 AND expires_at >= NOW()"#,
+            buffer_id.0
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+}
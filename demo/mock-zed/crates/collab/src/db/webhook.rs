@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use super::{EventSink, LockEvent};
+
+/// Delay before the first retry of a failed delivery, doubled on each attempt
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Posts lock events as JSON to an external HTTP endpoint
+///
+/// Deliveries run on their own task and are retried with exponential backoff;
+/// a delivery that still fails is logged and dropped without affecting the
+/// lock operation that produced it.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    max_retries: u32,
+}
+
+impl WebhookSink {
+    pub fn new(url: impl Into<String>, max_retries: u32) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+            max_retries,
+        }
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn send(&self, event: &LockEvent) {
+        let client = self.client.clone();
+        let url = self.url.clone();
+        let max_retries = self.max_retries;
+        let event = event.clone();
+
+        tokio::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+            for attempt in 0..=max_retries {
+                let response = client.post(&url).json(&event).send().await;
+                match response.and_then(|response| response.error_for_status()) {
+                    Ok(_) => return,
+                    Err(error) if attempt == max_retries => {
+                        tracing::error!(%error, %url, "giving up on lock event webhook delivery");
+                    }
+                    Err(error) => {
+                        tracing::warn!(%error, %url, attempt, "lock event webhook delivery failed");
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                    }
+                }
+            }
+        });
+    }
+}
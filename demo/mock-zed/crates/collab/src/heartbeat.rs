@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::Config;
+
+/// Largest jitter applied, so a delay never drops below a tenth of the base interval
+const MAX_HEARTBEAT_JITTER: f64 = 0.9;
+
+/// Returns how long a client should wait before its next presence heartbeat
+///
+/// The delay is `presence_heartbeat` shifted by up to `heartbeat_jitter` of
+/// itself in either direction, so clients that started together drift apart
+/// instead of hitting the database on the same tick. The jitter is capped at
+/// 0.9 so no client heartbeats back to back. Pass a seeded `rng` for
+/// reproducible delays.
+pub fn next_heartbeat_delay(config: &Config, rng: &mut impl Rng) -> Duration {
+    let jitter = config.heartbeat_jitter;
+    // `gen_range` would panic on a NaN range
+    if jitter.is_nan() || jitter <= 0.0 {
+        return config.presence_heartbeat;
+    }
+    let jitter = jitter.min(MAX_HEARTBEAT_JITTER);
+
+    let factor = rng.gen_range(1.0 - jitter..=1.0 + jitter);
+    config.presence_heartbeat.mul_f64(factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn config(jitter: f64) -> Config {
+        Config {
+            presence_heartbeat: Duration::from_secs(10),
+            heartbeat_jitter: jitter,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn delays_stay_within_the_jitter() {
+        let config = config(0.2);
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..1_000 {
+            let delay = next_heartbeat_delay(&config, &mut rng);
+            assert!(delay >= Duration::from_secs(8) && delay <= Duration::from_secs(12));
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_delays() {
+        let config = config(0.5);
+        let mut first = StdRng::seed_from_u64(42);
+        let mut second = StdRng::seed_from_u64(42);
+        for _ in 0..100 {
+            assert_eq!(
+                next_heartbeat_delay(&config, &mut first),
+                next_heartbeat_delay(&config, &mut second)
+            );
+        }
+    }
+
+    #[test]
+    fn full_jitter_never_gives_a_zero_delay() {
+        let config = config(1.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1_000 {
+            let delay = next_heartbeat_delay(&config, &mut rng);
+            assert!(delay >= Duration::from_millis(999) && delay <= Duration::from_secs(19));
+        }
+    }
+
+    #[test]
+    fn no_jitter_returns_the_base_interval() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for jitter in [0.0, -0.5, f64::NAN] {
+            assert_eq!(
+                next_heartbeat_delay(&config(jitter), &mut rng),
+                Duration::from_secs(10)
+            );
+        }
+    }
+}
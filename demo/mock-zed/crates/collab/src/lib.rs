@@ -0,0 +1,244 @@
+pub mod clock;
+pub mod config;
+pub mod connections;
+pub mod db;
+pub mod heartbeat;
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+pub use clock::Clock;
+pub use config::Config;
+pub use connections::ConnectionRegistry;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct BufferId(pub(crate) i64);
+
+impl BufferId {
+    /// Wraps a raw buffer id, rejecting zero and negative values
+    pub fn new(id: i64) -> Result<Self> {
+        if id <= 0 {
+            return Err(Error::InvalidId(id));
+        }
+
+        Ok(Self(id))
+    }
+
+    pub fn get(self) -> i64 {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct UserId(pub(crate) i64);
+
+impl UserId {
+    /// Wraps a raw user id, rejecting zero and negative values
+    pub fn new(id: i64) -> Result<Self> {
+        if id <= 0 {
+            return Err(Error::InvalidId(id));
+        }
+
+        Ok(Self(id))
+    }
+
+    pub fn get(self) -> i64 {
+        self.0
+    }
+}
+
+/// Identifies a buffer by its workspace and path instead of its integer id
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferKey {
+    pub workspace_id: i64,
+    pub path: String,
+}
+
+impl BufferKey {
+    pub fn new(workspace_id: i64, path: impl Into<String>) -> Self {
+        Self {
+            workspace_id,
+            path: path.into(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "buffer {} is locked{}",
+        .buffer_id.0,
+        .held_by.map(|user_id| format!(" by user {}", user_id.0)).unwrap_or_default()
+    )]
+    BufferLocked {
+        buffer_id: BufferId,
+        /// Holder when the acquisition was rejected, if it was still known
+        held_by: Option<UserId>,
+    },
+    #[error("timed out waiting for buffer lock")]
+    BufferLockTimeout,
+    #[error("buffer lock held by user {} has expired, confirm to take it over", .held_by.0)]
+    ExpiredLockNeedsConfirmation { held_by: UserId },
+    #[error("buffer is not locked by this user")]
+    NotLockHolder,
+    #[error("lock lease is stale, the lock has been granted again since it was issued")]
+    StaleLease,
+    #[error("buffer not found")]
+    NotFound,
+    #[error("buffer has been deleted")]
+    BufferDeleted,
+    #[error("locking this buffer would exceed the user's content quota")]
+    UserContentQuotaExceeded,
+    #[error("invalid id {0}, ids must be positive")]
+    InvalidId(i64),
+    #[error("invalid interval {0:?} for a periodic task")]
+    InvalidInterval(Duration),
+    #[error("lock acquisition was cancelled")]
+    Cancelled,
+    #[error("too many callers are already waiting for this buffer")]
+    WaitQueueFull,
+    #[error("buffer has changed since it was last read")]
+    VersionConflict,
+    #[error("lock service is degraded, try again later")]
+    ServiceDegraded,
+    #[error("too many buffers are locked, try again later")]
+    Overloaded,
+    #[error("the global limit on locked buffers has been reached")]
+    GlobalLockLimitReached,
+    #[error("buffers table is missing columns: {}", .missing.join(", "))]
+    SchemaMismatch { missing: Vec<String> },
+    #[error(
+        "failed to connect to {url} after {attempts} attempt(s): {}",
+        db::redact_url(&.source.to_string())
+    )]
+    Connect {
+        url: String,
+        attempts: u32,
+        source: sqlx::Error,
+    },
+    #[error("failed to migrate database: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("database error: {}", db::redact_url(&.0.to_string()))]
+    Database(#[from] sqlx::Error),
+}
+
+impl Error {
+    /// Whether the same request may succeed if retried later without changes
+    ///
+    /// True for unreachable databases, timeouts and temporary load shedding.
+    /// False for outcomes that won't change by themselves, such as a buffer
+    /// held by someone else: those need the caller to wait for the holder or
+    /// change what it asks for.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::BufferLockTimeout
+            | Error::WaitQueueFull
+            | Error::ServiceDegraded
+            | Error::Overloaded
+            | Error::GlobalLockLimitReached => true,
+            Error::Connect { source, .. } => db::is_unreachable(source),
+            Error::Database(error) => is_transient_database_error(error),
+            Error::BufferLocked { .. }
+            | Error::ExpiredLockNeedsConfirmation { .. }
+            | Error::NotLockHolder
+            | Error::StaleLease
+            | Error::NotFound
+            | Error::BufferDeleted
+            | Error::UserContentQuotaExceeded
+            | Error::InvalidId(_)
+            | Error::InvalidInterval(_)
+            | Error::Cancelled
+            | Error::VersionConflict
+            | Error::SchemaMismatch { .. }
+            | Error::Migrate(_) => false,
+        }
+    }
+}
+
+/// Connection failures, pool timeouts, and serialization failures or deadlocks
+/// that Postgres asks clients to retry
+fn is_transient_database_error(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(error) => {
+            matches!(error.code().as_deref(), Some("40001") | Some("40P01"))
+        }
+        _ => false,
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_accept_positive_values() {
+        assert_eq!(BufferId::new(1).unwrap().get(), 1);
+        assert_eq!(UserId::new(i64::MAX).unwrap().get(), i64::MAX);
+    }
+
+    #[test]
+    fn ids_reject_zero_and_negative_values() {
+        for id in [0, -1, i64::MIN] {
+            assert!(matches!(BufferId::new(id), Err(Error::InvalidId(rejected)) if rejected == id));
+            assert!(matches!(UserId::new(id), Err(Error::InvalidId(rejected)) if rejected == id));
+        }
+    }
+
+    #[test]
+    fn load_shedding_and_timeouts_are_retryable() {
+        for error in [
+            Error::BufferLockTimeout,
+            Error::WaitQueueFull,
+            Error::ServiceDegraded,
+            Error::Overloaded,
+            Error::GlobalLockLimitReached,
+        ] {
+            assert!(error.is_retryable(), "{error:?}");
+        }
+    }
+
+    #[test]
+    fn outcomes_that_need_the_caller_to_change_are_not_retryable() {
+        let buffer_id = BufferId(1);
+        let user_id = UserId(2);
+        for error in [
+            Error::BufferLocked { buffer_id, held_by: Some(user_id) },
+            Error::ExpiredLockNeedsConfirmation { held_by: user_id },
+            Error::NotLockHolder,
+            Error::StaleLease,
+            Error::NotFound,
+            Error::BufferDeleted,
+            Error::UserContentQuotaExceeded,
+            Error::InvalidId(0),
+            Error::InvalidInterval(Duration::ZERO),
+            Error::Cancelled,
+            Error::VersionConflict,
+            Error::SchemaMismatch { missing: vec!["lease_token".to_string()] },
+        ] {
+            assert!(!error.is_retryable(), "{error:?}");
+        }
+    }
+
+    #[test]
+    fn database_errors_are_retryable_only_when_transient() {
+        let unreachable = || sqlx::Error::Io(std::io::ErrorKind::ConnectionRefused.into());
+
+        assert!(Error::Database(unreachable()).is_retryable());
+        assert!(Error::Database(sqlx::Error::PoolTimedOut).is_retryable());
+        assert!(!Error::Database(sqlx::Error::RowNotFound).is_retryable());
+
+        let connect = |source| Error::Connect {
+            url: "postgres://host/db".to_string(),
+            attempts: 3,
+            source,
+        };
+        assert!(connect(unreachable()).is_retryable());
+        assert!(!connect(sqlx::Error::RowNotFound).is_retryable());
+    }
+}
@@ -0,0 +1,66 @@
+"""Tests that RACE-001 still plants a double-ownership race in mock-zed."""
+
+import re
+from pathlib import Path
+
+import pytest
+
+from sdlc_inject.catalog import PatternCatalog
+from sdlc_inject.injection import apply_patch
+
+ROOT = Path(__file__).resolve().parent.parent
+MOCK_ZED = ROOT / "demo" / "mock-zed"
+
+
+def function_body(source: str, name: str) -> str:
+    """Returns the text of `fn name` up to its closing brace at 4-space indent."""
+    match = re.search(rf"\n    (pub )?async fn {name}\b.*?\n    }}\n", source, re.DOTALL)
+    assert match, f"fn {name} not found"
+    return match.group(0)
+
+
+@pytest.fixture(scope="module")
+def injected() -> dict[str, str]:
+    pattern = PatternCatalog(ROOT / "patterns").get("RACE-001")
+    assert pattern is not None
+
+    files = {}
+    for file_inj in pattern.injection.files:
+        content = (MOCK_ZED / file_inj.path).read_text()
+        for patch in file_inj.patches:
+            content = apply_patch(content, patch)
+        files[file_inj.path] = content
+    return files
+
+
+class TestRace001Injection:
+    def test_anchor_is_unique_in_clean_tree(self):
+        buffers = (MOCK_ZED / "crates/collab/src/db/buffers.rs").read_text()
+        anchor = "let claim = self.try_acquire_lock(buffer_id, user_id, options).await?;"
+        assert buffers.count(anchor) == 1
+
+    def test_lock_path_checks_then_takes(self, injected):
+        body = function_body(injected["crates/collab/src/db/buffers.rs"], "lock_buffer")
+        assert "try_acquire_lock" not in body
+
+        check = body.index("self.check_buffer_available(buffer_id, user_id)")
+        sleep = body.index("std::thread::sleep")
+        take = body.index("self.take_checked_lock(buffer_id, user_id, options)")
+        assert check < sleep < take
+
+    def test_take_does_not_recheck_the_holder(self, injected):
+        body = function_body(injected["crates/collab/src/db/buffers.rs"], "take_checked_lock")
+        where = re.search(r"WHERE(.*?)RETURNING", body, re.DOTALL)
+        assert where
+        # Two callers that both passed the check both overwrite locked_by and
+        # both see the lock as acquired: the planted double ownership
+        assert where.group(1).split() == ["id", "=", "$1"]
+        assert "acquired: lease_token.is_some()" in body
+
+    def test_check_is_a_buffer_manager_method(self, injected):
+        module = injected["crates/collab/src/db/mod.rs"]
+        helper = module[module.index("impl BufferManager {"):]
+        body = function_body(helper, "check_buffer_available")
+        assert 'as "available!"' in body
+        assert "&self.pool" in body
+        assert "available.ok_or(Error::NotFound)" in body